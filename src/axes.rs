@@ -0,0 +1,311 @@
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+use crate::minmax;
+
+// Rate and release are worked out in fixed steps, however often update gets called
+const STEP: f32 = 1.0 / 250.0;
+// Give up on catching up after a stall longer than this
+const MAX_CATCH_UP: f32 = 0.25;
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Release {
+    // Straight back to centre
+    Instant,
+    // Back to centre at a steady speed, taking release_time from full deflection
+    Linear,
+    // Back to centre like a critically damped spring, settling in about release_time
+    Spring,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct AxisConfig {
+    // Deflection sets how fast the axis moves rather than where it is
+    pub rate: bool,
+    // Rate only: how much of the axis' range full deflection covers per second
+    pub rate_speed: f32,
+    // How the axis returns to centre when its grip is let go. Rate axes stay put.
+    pub release: Release,
+    // Seconds
+    pub release_time: f32,
+    // Added to the output, and where the axis returns to when let go
+    pub trim: f32,
+}
+
+impl Default for AxisConfig {
+    fn default() -> AxisConfig {
+        AxisConfig {
+            rate: false,
+            rate_speed: 1.0,
+            release: Release::Instant,
+            release_time: 0.3,
+            trim: 0.0,
+        }
+    }
+}
+
+// The flight axes, in the order they're passed around in: X, Y, Z, rX, rY, rZ
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct AxesConfig {
+    pub x: AxisConfig,
+    pub y: AxisConfig,
+    pub z: AxisConfig,
+    pub rx: AxisConfig,
+    pub ry: AxisConfig,
+    pub rz: AxisConfig,
+}
+
+impl AxesConfig {
+    fn all(&self) -> [&AxisConfig; 6] {
+        [&self.x, &self.y, &self.z, &self.rx, &self.ry, &self.rz]
+    }
+
+    fn all_mut(&mut self) -> [&mut AxisConfig; 6] {
+        [
+            &mut self.x,
+            &mut self.y,
+            &mut self.z,
+            &mut self.rx,
+            &mut self.ry,
+            &mut self.rz,
+        ]
+    }
+
+    pub fn trims(&self) -> [f32; 6] {
+        let mut trims = [0.0; 6];
+        for (trim, axis) in trims.iter_mut().zip(self.all().iter()) {
+            *trim = axis.trim;
+        }
+        trims
+    }
+}
+
+pub struct Axes {
+    // Before trim
+    values: [f32; 6],
+    output: [f32; 6],
+    // Only used by spring release
+    velocities: [f32; 6],
+    last: Option<Instant>,
+    accumulator: f32,
+}
+
+impl Axes {
+    pub fn new() -> Axes {
+        Axes {
+            values: [0.0; 6],
+            output: [0.0; 6],
+            velocities: [0.0; 6],
+            last: None,
+            accumulator: 0.0,
+        }
+    }
+
+    // Turns deflection from -1 to 1 into output values from -1 to 1. Held
+    // axes are the ones whose hand is gripping.
+    pub fn update(
+        &mut self,
+        config: &AxesConfig,
+        deflection: [f32; 6],
+        held: [bool; 6],
+    ) -> [f32; 6] {
+        let now = Instant::now();
+        let elapsed = match self.last {
+            Some(last) => (now - last).as_secs_f32(),
+            None => 0.0,
+        };
+        self.last = Some(now);
+        self.step(config, deflection, held, elapsed)
+    }
+
+    fn step(
+        &mut self,
+        config: &AxesConfig,
+        deflection: [f32; 6],
+        held: [bool; 6],
+        elapsed: f32,
+    ) -> [f32; 6] {
+        self.accumulator = (self.accumulator + elapsed).min(MAX_CATCH_UP);
+        let mut steps = 0;
+        while self.accumulator >= STEP {
+            self.accumulator -= STEP;
+            steps += 1;
+        }
+
+        for (i, axis) in config.all().iter().enumerate() {
+            if axis.rate {
+                for _ in 0..steps {
+                    self.values[i] = minmax(
+                        self.values[i] + deflection[i] * axis.rate_speed * STEP,
+                        -1.0,
+                        1.0,
+                    );
+                }
+            } else if held[i] {
+                self.values[i] = deflection[i];
+                self.velocities[i] = 0.0;
+            } else {
+                for _ in 0..steps {
+                    self.release(i, axis);
+                }
+                if axis.release == Release::Instant {
+                    self.values[i] = 0.0;
+                }
+            }
+            self.output[i] = minmax(self.values[i] + axis.trim, -1.0, 1.0);
+        }
+        self.output
+    }
+
+    // Values are kept relative to the trim, so returning to 0 returns to the trim
+    fn release(&mut self, i: usize, axis: &AxisConfig) {
+        let value = &mut self.values[i];
+        let velocity = &mut self.velocities[i];
+        let time = axis.release_time.max(STEP);
+        match axis.release {
+            Release::Instant => *value = 0.0,
+            Release::Linear => *value = value.signum() * (value.abs() - STEP / time).max(0.0),
+            Release::Spring => {
+                let omega = 6.0 / time;
+                let acceleration = -omega * omega * *value - 2.0 * omega * *velocity;
+                *velocity += acceleration * STEP;
+                *value += *velocity * STEP;
+            }
+        }
+    }
+
+    // Makes the current output of the chosen axes their new trim
+    pub fn trim(&mut self, config: &mut AxesConfig, axes: [bool; 6]) {
+        for (i, axis) in config.all_mut().iter_mut().enumerate() {
+            if axes[i] {
+                axis.trim = self.output[i];
+                self.values[i] = 0.0;
+                self.velocities[i] = 0.0;
+            }
+        }
+    }
+
+    pub fn clear_trim(&mut self, config: &mut AxesConfig) {
+        for (i, axis) in config.all_mut().iter_mut().enumerate() {
+            self.values[i] = minmax(self.values[i] + axis.trim, -1.0, 1.0);
+            axis.trim = 0.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HELD: [bool; 6] = [true; 6];
+
+    fn rate_config() -> AxesConfig {
+        let mut config = AxesConfig::default();
+        config.x.rate = true;
+        config.x.rate_speed = 0.5;
+        config
+    }
+
+    #[test]
+    fn position_axes_pass_through() {
+        let mut axes = Axes::new();
+        let output = axes.step(&rate_config(), [0.3, -0.2, 0.0, 1.0, 0.0, 0.0], HELD, 0.1);
+        assert_eq!(&output[1..], &[-0.2, 0.0, 1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn rate_axis_integrates_and_holds() {
+        let mut axes = Axes::new();
+        let config = rate_config();
+        let moving = axes.step(&config, [1.0, 0.0, 0.0, 0.0, 0.0, 0.0], HELD, 0.2);
+        assert!((moving[0] - 0.1).abs() < 0.01);
+        let held = axes.step(&config, [0.0; 6], HELD, 1.0);
+        assert_eq!(held[0], moving[0]);
+    }
+
+    #[test]
+    fn rate_axis_is_clamped() {
+        let mut axes = Axes::new();
+        let config = rate_config();
+        for _ in 0..100 {
+            axes.step(&config, [-1.0, 0.0, 0.0, 0.0, 0.0, 0.0], HELD, 0.1);
+        }
+        assert_eq!(axes.step(&config, [0.0; 6], HELD, 0.0)[0], -1.0);
+    }
+
+    #[test]
+    fn rate_does_not_depend_on_update_frequency() {
+        let config = rate_config();
+        let deflection = [1.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+        let mut fast = Axes::new();
+        for _ in 0..90 {
+            fast.step(&config, deflection, HELD, 1.0 / 90.0);
+        }
+        let mut slow = Axes::new();
+        for _ in 0..30 {
+            slow.step(&config, deflection, HELD, 1.0 / 30.0);
+        }
+        let fast = fast.step(&config, [0.0; 6], HELD, 0.0)[0];
+        let slow = slow.step(&config, [0.0; 6], HELD, 0.0)[0];
+        assert!((fast - slow).abs() <= 0.5 * STEP + 1e-6);
+    }
+
+    fn released(release: Release) -> Vec<f32> {
+        let mut config = AxesConfig::default();
+        config.x.release = release;
+        config.x.release_time = 0.2;
+        let mut axes = Axes::new();
+        axes.step(&config, [1.0, 0.0, 0.0, 0.0, 0.0, 0.0], HELD, 0.0);
+        (0..50)
+            .map(|_| axes.step(&config, [0.0; 6], [false; 6], 0.01)[0])
+            .collect()
+    }
+
+    #[test]
+    fn instant_release_centres_at_once() {
+        assert_eq!(released(Release::Instant)[0], 0.0);
+    }
+
+    #[test]
+    fn linear_release_takes_release_time() {
+        let values = released(Release::Linear);
+        assert!((values[9] - 0.5).abs() < 0.03);
+        assert_eq!(values[20], 0.0);
+    }
+
+    #[test]
+    fn spring_release_settles_without_overshoot() {
+        let values = released(Release::Spring);
+        assert!(values.iter().all(|&value| value >= 0.0));
+        assert!(values[0] > 0.9);
+        assert!(values[29] < 0.02);
+    }
+
+    #[test]
+    fn released_axis_returns_to_trim() {
+        let mut config = AxesConfig::default();
+        let mut axes = Axes::new();
+        axes.step(&config, [0.0, 0.0, 0.0, 0.2, 0.0, 0.0], HELD, 0.0);
+        axes.trim(&mut config, [false, false, false, true, false, false]);
+        assert_eq!(config.rx.trim, 0.2);
+        let held = axes.step(&config, [0.0, 0.0, 0.0, 0.5, 0.0, 0.0], HELD, 0.01);
+        assert!((held[3] - 0.7).abs() < 1e-6);
+        let released = axes.step(&config, [0.0; 6], [false; 6], 0.01);
+        assert_eq!(released[3], 0.2);
+    }
+
+    #[test]
+    fn clearing_trim_keeps_rate_axis_in_place() {
+        let mut config = rate_config();
+        let mut axes = Axes::new();
+        axes.step(&config, [1.0, 0.0, 0.0, 0.0, 0.0, 0.0], HELD, 0.2);
+        axes.trim(&mut config, [true, false, false, false, false, false]);
+        axes.clear_trim(&mut config);
+        let output = axes.step(&config, [0.0; 6], HELD, 0.0);
+        assert_eq!(config.x.trim, 0.0);
+        assert!((output[0] - 0.1).abs() < 0.01);
+    }
+}
@@ -4,34 +4,64 @@ use ovr_sys::*;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::env::args;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Mutex;
+use std::thread;
 
-use ggez::nalgebra::{Point3, Rotation3};
-use ggez::{event, graphics, nalgebra as na, Context, GameResult};
+use ggez::nalgebra::Rotation3;
+use ggez::{event, GameError, GameResult};
 
 use clap::Clap;
 
 use std::f32::consts::PI;
 
+#[macro_use]
+mod log;
+mod axes;
+mod calibration;
+mod cockpit;
+mod curve;
+mod error;
+mod gesture;
+mod hand;
+mod mapping;
+mod mouse;
+mod oculus;
+mod pointer;
+mod profile;
+mod retry;
+mod timer;
+mod tracking;
+mod transform;
 mod vjoy;
+mod watchdog;
+mod wheel;
+mod window;
+mod yoke;
+use axes::Axes;
+use calibration::Calibration;
+use cockpit::Cockpit;
+use error::Error;
+use gesture::{Recogniser, Recorder, Sample};
+use hand::{HandConfig, Style};
+use mapping::Mapping;
+use profile::{Mode, Profile, TouchButton};
+use retry::Retry;
+use timer::Timer;
+use tracking::Tracking;
+use transform::Transform;
 use vjoy::{Axis, Joystick, PovDirection};
+use watchdog::Watchdog;
+use wheel::Wheel;
+use window::{Request, Snapshot, Window};
 
-const RANGE: f32 = 32768.0;
 const MAX_ANGLE: f32 = PI / 4.0;
 const MAX_THROTTLE: f32 = 20.0;
-
-fn ovr_try<F>(f: F) -> Result<(), Box<ovrErrorInfo>>
-where
-    F: FnOnce() -> ovrResult,
-{
-    let result = f();
-    if OVR_SUCCESS(result) {
-        Ok(())
-    } else {
-        let mut info = Box::new(unsafe { ::std::mem::zeroed() });
-        unsafe { ovr_GetLastErrorInfo(&mut *info as *mut _) }
-        Err(info)
-    }
-}
+const FLIGHT_AXES: [Axis; 6] = [Axis::X, Axis::Y, Axis::Z, Axis::RX, Axis::RY, Axis::RZ];
+const SWITCH_MODE: i32 = ovrButton_LThumb | ovrButton_RThumb;
 
 #[derive(Clap)]
 #[clap(version = "1.0", author = "Luke Tuthill <lukemtuthill@gmail.com>")]
@@ -41,7 +71,31 @@ struct Opts {
     #[clap(short, long)]
     hatbuttons: bool,
     #[clap(short, long)]
-    triggerbuttons: bool
+    triggerbuttons: bool,
+    #[clap(short, long, default_value = "ocujoy.toml", parse(from_os_str))]
+    profile: PathBuf,
+    #[clap(long, parse(from_os_str))]
+    record_gestures: Option<PathBuf>,
+    #[clap(long, parse(from_os_str))]
+    replay_gestures: Option<PathBuf>,
+    /// Run without the debug window
+    #[clap(long)]
+    headless: bool,
+    /// Append everything printed to a file as well
+    #[clap(long, parse(from_os_str))]
+    log: Option<PathBuf>,
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Clap)]
+enum Command {
+    /// Place the cockpit controls from the profile while sat in VR, then save it
+    PlaceControls,
+    /// Record where the throttle and stick rest for anchored hands, then save the profile
+    SetAnchors,
+    /// Record how far each hand comfortably moves and twists, then save the profile
+    CalibrateRanges,
 }
 
 #[derive(Debug)]
@@ -69,38 +123,19 @@ impl From<Rotation3<f32>> for EulerRotation {
     }
 }
 
-#[derive(Debug, Clone)]
-struct Transform {
-    pos: Point3<f32>,
-    rot: na::UnitQuaternion<f32>,
-}
-
-impl Transform {
-    fn new(pose: ovrPosef) -> Transform {
-        let rot = na::UnitQuaternion::new_normalize(na::Quaternion::new(
-            pose.Orientation.w,
-            pose.Orientation.x,
-            pose.Orientation.y,
-            pose.Orientation.z,
-        ));
-        let pos: Point3<f32> = Point3::from_slice(&[
-            pose.Position.x * 100.0,
-            pose.Position.y * 100.0,
-            pose.Position.z * 100.0,
-        ]);
-        Transform { pos, rot }
-    }
-    fn default() -> Transform {
-        Transform {
-            pos: Point3::origin(),
-            rot: na::UnitQuaternion::identity(),
-        }
-    }
-}
-
 struct MainState {
-    session: ovrSession,
+    session: Option<ovrSession>,
     joystick: Joystick,
+    acquired: bool,
+    // The axes that rest at their minimum in the current mode, shared with the watchdog
+    resting: Arc<Mutex<Vec<Axis>>>,
+    oculus_retry: Retry,
+    vjoy_retry: Retry,
+    // The last failed attempt at each, cleared once it succeeds
+    oculus_error: Option<Error>,
+    vjoy_error: Option<Error>,
+    // vJoy write failures already logged since the device was acquired
+    reported: Vec<Error>,
     left: Transform,
     right: Transform,
     left_ref: Option<Transform>,
@@ -111,7 +146,28 @@ struct MainState {
     x: f32,
     y: f32,
     z: f32,
+    steering: f32,
+    cursor: (f32, f32),
+    clicking: bool,
+    // Per hand, for hands with the latched throttle style
+    latched: [f32; 2],
+    latched_start: [Option<f32>; 2],
+    triggers: [f32; 2],
+    mode: Mode,
+    wheel: Wheel,
+    axes: Axes,
+    tracking: Tracking,
+    watchdog: Watchdog,
     vibration: Vibration,
+    gestures: Option<Recogniser>,
+    recorder: Option<Recorder>,
+    cockpit: Cockpit,
+    calibration: Option<Calibration>,
+    last_buttons: i32,
+    paused: bool,
+    shut_down: bool,
+    stop: Arc<AtomicBool>,
+    profile: Profile,
     opts: Opts
 }
 
@@ -196,15 +252,63 @@ impl Vibration {
     }
 }
 
+fn set_haptics(session: ovrSession, hand: usize, amplitude: f32) {
+    let controller = [ovrControllerType_LTouch, ovrControllerType_RTouch][hand];
+    unsafe {
+        ovr_SetControllerVibration(session, controller, 1.0, amplitude);
+    }
+}
+
 fn angle_from_vector(vector: ovrVector2f) -> f32 {
     (vector.y).atan2(-vector.x) 
 }
 
 impl MainState {
-    fn new(session: ovrSession, joystick: Joystick, opts: Opts) -> GameResult<MainState> {
+    fn new(
+        mut joystick: Joystick,
+        profile: Profile,
+        opts: Opts,
+        stop: Arc<AtomicBool>,
+    ) -> GameResult<MainState> {
+        let gestures = match profile.gestures.enabled {
+            true => Some(Recogniser::new(profile.gestures.clone())),
+            false => None,
+        };
+        let recorder = match &opts.record_gestures {
+            Some(path) => Some(Recorder::create(path)?),
+            None => None,
+        };
+        let calibration = match &opts.command {
+            Some(Command::PlaceControls) => Some(Calibration::controls()),
+            Some(Command::SetAnchors) => Some(Calibration::anchors()),
+            Some(Command::CalibrateRanges) => Some(Calibration::ranges()),
+            None => None,
+        };
+        if let Some(calibration) = &calibration {
+            log!("{}", calibration.prompt(&profile));
+        }
+        let resting = profile.resting_axes(profile.mode, opts.input && !opts.triggerbuttons);
+        joystick.rest_at_minimum(resting.clone());
+        let resting = Arc::new(Mutex::new(resting));
+        let watchdog = {
+            let resting = resting.clone();
+            let mut watched = Joystick::new(joystick.device());
+            Watchdog::spawn(profile.watchdog_timeout, move || {
+                // As of whatever mode ocujoy is in when it goes off
+                watched.rest_at_minimum(resting.lock().unwrap().clone());
+                watched.neutral();
+            })
+        };
         Ok(MainState {
-            session,
+            session: None,
             joystick,
+            acquired: false,
+            resting,
+            oculus_retry: Retry::new(),
+            vjoy_retry: Retry::new(),
+            oculus_error: None,
+            vjoy_error: None,
+            reported: Vec::new(),
             left: Transform::default(),
             right: Transform::default(),
             left_ref: None,
@@ -215,26 +319,702 @@ impl MainState {
             x: 0.0,
             y: 0.0,
             z: 0.0,
+            steering: 0.0,
+            cursor: (0.0, 0.0),
+            clicking: false,
+            latched: [0.0, 0.0],
+            latched_start: [None, None],
+            triggers: [0.0, 0.0],
+            mode: profile.mode,
+            wheel: Wheel::new(),
+            axes: Axes::new(),
+            tracking: Tracking::new(),
+            watchdog,
             vibration: Vibration::new(),
+            gestures,
+            recorder,
+            cockpit: Cockpit::new(&profile.controls),
+            calibration,
+            last_buttons: 0,
+            paused: false,
+            shut_down: false,
+            stop,
+            profile,
             opts: opts
         })
     }
 
+    fn calibrate(&mut self, pressed: [bool; 2]) {
+        let calibration = match &mut self.calibration {
+            Some(calibration) => calibration,
+            None => return,
+        };
+        if calibration.update(&mut self.profile, [&self.left, &self.right], pressed) {
+            self.calibration = None;
+            self.save_profile();
+            self.cockpit = Cockpit::new(&self.profile.controls);
+        } else if pressed.contains(&true) {
+            log!("{}", calibration.prompt(&self.profile));
+        }
+    }
+
+    // Leaves the game with nothing held down, then lets go of vJoy and the
+    // Oculus session. Safe to call more than once.
+    fn shutdown(&mut self) {
+        if self.shut_down {
+            return;
+        }
+        self.shut_down = true;
+        log!("Quitting");
+        // Closes the window too, if the mapping thread is going first
+        self.stop.store(true, Ordering::SeqCst);
+        self.watchdog.stop();
+        if self.clicking {
+            mouse::set_left_button(false);
+            self.clicking = false;
+        }
+        if self.acquired {
+            self.joystick.neutral();
+            self.joystick.relinquish();
+        }
+        if let Some(session) = self.session.take() {
+            oculus::disconnect(session);
+        }
+    }
+
+    // Connects to the Oculus runtime and acquires vJoy, trying again every so
+    // often if either isn't there or goes away. Returns the session if there is
+    // one, whether or not vJoy is acquired, so its status is still kept up with.
+    fn supervise(&mut self) -> Option<ovrSession> {
+        if self.acquired && !self.joystick.is_owned() {
+            log!("Lost vJoy device {}", self.joystick.device());
+            self.acquired = false;
+        }
+        if !self.acquired && self.vjoy_retry.due() {
+            match self.joystick.acquire() {
+                Ok(()) => {
+                    log!("Acquired vJoy device {}", self.joystick.device());
+                    self.check_mappings();
+                    self.acquired = true;
+                    self.vjoy_retry.succeeded();
+                    self.vjoy_error = None;
+                    self.reported.clear();
+                    // Nothing else is sent while calibrating or waiting for the
+                    // Oculus runtime, so don't leave the game with whatever the
+                    // device last held
+                    self.centre_axes();
+                }
+                Err(e) => {
+                    let delay = self.vjoy_retry.failed();
+                    log!("{} (trying again in {}s)", e, delay.as_secs());
+                    self.vjoy_error = Some(e);
+                }
+            }
+        }
+        if self.session.is_none() && self.oculus_retry.due() {
+            match oculus::connect() {
+                Ok(session) => {
+                    log!("Connected to the Oculus runtime");
+                    self.session = Some(session);
+                    self.oculus_retry.succeeded();
+                    self.oculus_error = None;
+                }
+                Err(e) => {
+                    let delay = self.oculus_retry.failed();
+                    log!("{} (trying again in {}s)", e, delay.as_secs());
+                    self.oculus_error = Some(e);
+                }
+            }
+        }
+        self.session
+    }
+
+    // Everything ocujoy will write to vJoy with this profile and these options
+    fn mappings(&self) -> Vec<Mapping> {
+        let mut mappings = Vec::new();
+        let modes: Vec<Mode> = self
+            .profile
+            .modes
+            .iter()
+            .cloned()
+            .chain(Some(self.profile.mode))
+            .collect();
+        for (&axis, name) in FLIGHT_AXES
+            .iter()
+            .zip(["X", "Y", "Z", "pitch", "roll", "yaw"].iter())
+        {
+            let mut mapping = Mapping::axis(axis, name);
+            for &mode in modes.iter() {
+                if self.profile.mode_axes(mode).contains(&axis) {
+                    mapping = mapping.except_in(mode);
+                }
+            }
+            mappings.push(mapping);
+        }
+        for (config, name) in [&self.profile.left, &self.profile.right]
+            .iter()
+            .zip(["left", "right"].iter())
+        {
+            if config.style == Style::LatchedThrottle {
+                let purpose = format!("{} hand latched throttle", name);
+                let mapping = Mapping::axis(config.latched_axis, &purpose);
+                mappings.push(mapping.only_in(Mode::StickThrottle));
+            }
+        }
+        if modes.contains(&Mode::Wheel) {
+            let wheel = &self.profile.wheel;
+            for (&axis, purpose) in [wheel.steering_axis, wheel.throttle_axis, wheel.brake_axis]
+                .iter()
+                .zip(["wheel steering", "wheel throttle", "wheel brake"].iter())
+            {
+                mappings.push(Mapping::axis(axis, purpose).only_in(Mode::Wheel));
+            }
+        }
+        if modes.contains(&Mode::Pointer) && !self.profile.pointer.mouse {
+            let pointer = &self.profile.pointer;
+            mappings.push(Mapping::axis(pointer.x_axis, "pointer X").only_in(Mode::Pointer));
+            mappings.push(Mapping::axis(pointer.y_axis, "pointer Y").only_in(Mode::Pointer));
+        }
+        for control in self.profile.controls.iter() {
+            let purpose = format!("control {}", control.name);
+            if control.button != 0 {
+                mappings.push(Mapping::button(control.button, &purpose));
+            }
+            if let Some(axis) = control.axis {
+                mappings.push(Mapping::axis(axis, &purpose));
+            }
+        }
+        let gestures = &self.profile.gestures;
+        if gestures.enabled {
+            let buttons = [
+                ("flick", gestures.flick_buttons),
+                ("punch", gestures.punch_buttons),
+                ("twist", gestures.twist_buttons),
+                ("swipe left", gestures.swipe_left_buttons),
+                ("swipe right", gestures.swipe_right_buttons),
+            ];
+            for (name, hands) in buttons.iter() {
+                for (&button, hand) in hands.iter().zip(["left", "right"].iter()) {
+                    if button != 0 {
+                        let purpose = format!("{} {} gesture", hand, name);
+                        mappings.push(Mapping::button(button, &purpose));
+                    }
+                }
+            }
+        }
+        if self.opts.input {
+            let buttons = [
+                "A",
+                "B",
+                "X",
+                "Y",
+                "left stick click",
+                "right stick click",
+                "menu",
+            ];
+            for (i, name) in buttons.iter().enumerate() {
+                mappings.push(Mapping::button(i as u8 + 1, name));
+            }
+            if self.opts.triggerbuttons {
+                mappings.push(Mapping::button(8, "left trigger"));
+                mappings.push(Mapping::button(9, "right trigger"));
+            } else {
+                mappings.push(Mapping::axis(Axis::SL0, "left trigger"));
+                mappings.push(Mapping::axis(Axis::SL1, "right trigger"));
+            }
+            for (pov, name) in [(1, "left thumbstick"), (2, "right thumbstick")].iter() {
+                if self.opts.hatbuttons {
+                    for i in 0..4 {
+                        mappings.push(Mapping::button(10 + 4 * (pov - 1) + i, name));
+                    }
+                } else {
+                    mappings.push(Mapping::pov(*pov, name));
+                }
+            }
+        }
+        mappings
+    }
+
+    // Logs everything the profile maps that the vJoy device doesn't have, and
+    // anything mapped twice
+    fn check_mappings(&self) {
+        let device = self.joystick.device();
+        let capabilities = self.joystick.capabilities();
+        log!(
+            "vJoy device {} has {} axes, {} buttons, {} discrete and {} continuous hats",
+            device,
+            capabilities.axes.len(),
+            capabilities.buttons,
+            capabilities.discrete_povs,
+            capabilities.continuous_povs
+        );
+        let unsatisfied = mapping::check(device, &self.mappings(), capabilities);
+        for (error, purpose) in unsatisfied.iter() {
+            log!("{}, needed for {}", error, purpose);
+        }
+        let hats_missing = unsatisfied
+            .iter()
+            .any(|(error, _)| matches!(error, Error::PovOutOfRange(..)));
+        if hats_missing && capabilities.continuous_povs > 0 {
+            log!("ocujoy only uses discrete hats, change them in Configure vJoy");
+        }
+        if !unsatisfied.is_empty() {
+            log!("Enable the missing controls in Configure vJoy, or change the profile");
+        }
+        for (output, first, second) in mapping::clashes(&self.mappings()) {
+            log!("{} is used for both {} and {}", output, first, second);
+        }
+        let trim = self.profile.trim_button;
+        if self.opts.input && trim != TouchButton::None {
+            log!(
+                "The trim button {:?} is also button {} with --input, so trimming presses it too",
+                trim,
+                trim.input_button()
+            );
+            log!("Set trim_button = \"none\" to trim from the debug window only");
+        }
+    }
+
+    // The session can't be used any more, e.g. the Oculus service restarted
+    fn lose_session(&mut self) {
+        if let Some(session) = self.session.take() {
+            log!("Lost the Oculus session");
+            oculus::disconnect(session);
+        }
+        self.centre_axes();
+    }
+
+    // Sends everything written to vJoy this tick as one report, and feeds the
+    // watchdog once it has gone out
+    fn flush(&mut self) {
+        if !self.acquired {
+            return;
+        }
+        let sent = self.joystick.flush();
+        if sent.is_err() {
+            self.output(sent);
+            return;
+        }
+        // The watchdog centred the device behind the report's back, so send it again
+        if self.watchdog.feed() {
+            self.joystick.invalidate();
+            let sent = self.joystick.flush();
+            self.output(sent);
+        }
+    }
+
+    // Deals with a failed vJoy write. Losing the device means acquiring it again;
+    // anything else is most likely the profile asking for an axis, button or hat
+    // the device doesn't have, so it's only logged the first time.
+    fn output(&mut self, result: Result<(), Error>) {
+        let e = match result {
+            Ok(()) => return,
+            Err(e) => e,
+        };
+        if let Error::NotAcquired(_) = e {
+            log!("{}, acquiring it again", e);
+            self.acquired = false;
+            return;
+        }
+        if !self.reported.contains(&e) {
+            log!("{}", e);
+            self.reported.push(e);
+        }
+    }
+
+    fn set_axis(&mut self, axis: Axis, value: f32) {
+        let result = self.joystick.set_axis(axis, value);
+        self.output(result);
+    }
+
+    fn set_axis_unit(&mut self, axis: Axis, value: f32) {
+        let result = self.joystick.set_axis_unit(axis, value);
+        self.output(result);
+    }
+
+    fn set_btn(&mut self, button: u8, state: bool) {
+        let result = self.joystick.set_btn(button, state);
+        self.output(result);
+    }
+
+    fn set_pov(&mut self, pov: u8, value: PovDirection) {
+        let result = self.joystick.set_pov(pov, value);
+        self.output(result);
+    }
+
+    // Drops both grips and sends a neutral report: the flight axes in the
+    // middle ignoring trim, triggers and throttles at their minimum, and every
+    // button and hat at rest
+    fn centre_axes(&mut self) {
+        self.left_ref = None;
+        self.right_ref = None;
+        self.x = 0.0;
+        self.y = 0.0;
+        self.z = 0.0;
+        self.pitch = 0.0;
+        self.roll = 0.0;
+        self.yaw = 0.0;
+        self.joystick.clear();
+        self.flush();
+    }
+
+    // Returns false if ocujoy should quit
+    fn check_session(&mut self, session: ovrSession) -> bool {
+        let mut status: ovrSessionStatus = unsafe { ::std::mem::zeroed() };
+        let result = unsafe { ovr_GetSessionStatus(session, &mut status as *mut _) };
+        if !OVR_SUCCESS(result) || status.DisplayLost != 0 {
+            self.lose_session();
+            return true;
+        }
+        if status.ShouldQuit != 0 {
+            log!("Oculus asked ocujoy to quit");
+            return false;
+        }
+        if status.ShouldRecenter != 0 {
+            unsafe {
+                ovr_RecenterTrackingOrigin(session);
+            }
+            self.left_ref = None;
+            self.right_ref = None;
+            log!("Recentred");
+        }
+        let reason = if self.profile.pause_when_unmounted && status.HmdMounted == 0 {
+            Some("Headset off")
+        } else if self.profile.pause_when_unfocused && status.HasInputFocus == 0 {
+            Some("Lost input focus")
+        } else {
+            None
+        };
+        if reason.is_some() != self.paused {
+            self.paused = reason.is_some();
+            match reason {
+                Some(reason) => {
+                    log!("{}, pausing", reason);
+                    self.centre_axes();
+                }
+                None => log!("Resuming"),
+            }
+        }
+        true
+    }
+
+    fn save_profile(&self) {
+        match self.profile.save(&self.opts.profile) {
+            Ok(()) => log!("Saved profile to {}", self.opts.profile.display()),
+            Err(e) => log!("{}", e),
+        }
+    }
+
+    // Trims the axes being held to where they are now, or clears all the trim
+    // if nothing is held
+    fn trim(&mut self) {
+        let held = self.held_axes();
+        if held.contains(&true) {
+            self.axes.trim(&mut self.profile.axes, held);
+            // Regrab from here so the held axes carry on from the trim
+            if self.left_ref.is_some() {
+                self.left_ref = Some(self.profile.left.regrab(&self.left));
+            }
+            if self.right_ref.is_some() {
+                self.right_ref = Some(self.profile.right.regrab(&self.right));
+            }
+            self.latched_start = [None, None];
+            log!("Trim: {:?}", self.profile.axes.trims());
+        } else {
+            self.axes.clear_trim(&mut self.profile.axes);
+            log!("Trim cleared");
+        }
+        self.save_profile();
+    }
+
+    fn next_mode(&mut self) {
+        let modes = &self.profile.modes;
+        let next = modes
+            .iter()
+            .position(|&mode| mode == self.mode)
+            .map_or(0, |i| (i + 1) % modes.len());
+        if let Some(&mode) = modes.get(next) {
+            // Nothing writes the old mode's own axes any more, so put them back at rest
+            for axis in self.profile.mode_axes(self.mode) {
+                self.joystick.rest_axis(axis);
+            }
+            self.mode = mode;
+            self.update_resting();
+            self.left_ref = None;
+            self.right_ref = None;
+            if self.clicking {
+                mouse::set_left_button(false);
+                self.clicking = false;
+            }
+            log!("Mode: {:?}", mode);
+        }
+    }
+
+    fn update_resting(&mut self) {
+        let trigger_axes = self.opts.input && !self.opts.triggerbuttons;
+        let resting = self.profile.resting_axes(self.mode, trigger_axes);
+        self.joystick.rest_at_minimum(resting.clone());
+        *self.resting.lock().unwrap() = resting;
+    }
+
+    fn hand(&self, hand: usize) -> (&HandConfig, &Transform, Option<&Transform>) {
+        match hand {
+            0 => (&self.profile.left, &self.left, self.left_ref.as_ref()),
+            _ => (&self.profile.right, &self.right, self.right_ref.as_ref()),
+        }
+    }
+
+    // Flight axes the hand drives in the current mode
+    fn hand_axes(&self, hand: usize) -> [bool; 6] {
+        match self.mode {
+            Mode::StickThrottle => self.hand(hand).0.style.axes(hand),
+            Mode::Yoke => [false, false, false, true, true, true],
+            Mode::Wheel | Mode::Pointer => [false; 6],
+        }
+    }
+
+    // Flight axes whose hand is gripping, so the rest can return to centre
+    fn held_axes(&self) -> [bool; 6] {
+        let gripping = match self.mode {
+            Mode::Yoke => [self.left_ref.is_some() && self.right_ref.is_some(); 2],
+            _ => [self.left_ref.is_some(), self.right_ref.is_some()],
+        };
+        let mut held = [false; 6];
+        for hand in 0..2 {
+            if gripping[hand] {
+                for (held, &axis) in held.iter_mut().zip(self.hand_axes(hand).iter()) {
+                    *held |= axis;
+                }
+            }
+        }
+        held
+    }
+
+    fn update_stick_throttle(&mut self) {
+        self.x = 0.0;
+        self.y = 0.0;
+        self.z = 0.0;
+        self.pitch = 0.0;
+        self.roll = 0.0;
+        self.yaw = 0.0;
+        for hand in 0..2 {
+            let style = self.hand(hand).0.style;
+            match style {
+                Style::Standard if hand == 0 => self.update_throttle(),
+                Style::Standard => self.update_stick(),
+                Style::Collective => self.update_collective(hand),
+                Style::Cyclic => self.update_cyclic(hand),
+                Style::SixDof => self.update_six_dof(hand),
+                Style::LatchedThrottle => self.update_latched(hand),
+                Style::Unused => (),
+            }
+        }
+    }
+
+    fn update_six_dof(&mut self, hand: usize) {
+        let (config, current, reference) = self.hand(hand);
+        let axes = reference.map(|reference| config.six_dof(reference, current));
+        if let Some(axes) = axes {
+            self.x = axes[0];
+            self.y = axes[1];
+            self.z = axes[2];
+            self.pitch = axes[3] * MAX_ANGLE;
+            self.roll = axes[4] * MAX_ANGLE;
+            self.yaw = axes[5] * MAX_ANGLE;
+        }
+    }
+
+    fn update_latched(&mut self, hand: usize) {
+        let (config, current, reference) = self.hand(hand);
+        // Forward is -z, so pushing uses range_min[2] and pulling range_max[2]
+        let push = reference.map(|reference| -config.normalise(2, current.pos.z - reference.pos.z));
+        match push {
+            Some(push) => {
+                let start = *self.latched_start[hand].get_or_insert(self.latched[hand]);
+                self.latched[hand] = minmax(start + push, 0.0, 1.0);
+            }
+            None => self.latched_start[hand] = None,
+        }
+    }
+
+    fn update_collective(&mut self, hand: usize) {
+        let (config, current, reference) = self.hand(hand);
+        let collective = reference.map_or(0.0, |reference| config.collective(reference, current));
+        self.x = 0.0;
+        self.y = collective;
+        self.z = 0.0;
+    }
+
+    fn update_cyclic(&mut self, hand: usize) {
+        let (config, current, reference) = self.hand(hand);
+        let (pitch, roll) =
+            reference.map_or((0.0, 0.0), |reference| config.cyclic(reference, current));
+        self.pitch = pitch * MAX_ANGLE;
+        self.roll = roll * MAX_ANGLE;
+        self.yaw = 0.0;
+    }
+
+    fn update_throttle(&mut self) {
+        if let Some(left_ref_point) = &self.left_ref {
+            let axes = self.profile.left.normalised(left_ref_point, &self.left);
+            self.x = logarize(axes[0]);
+            self.y = logarize(axes[1]);
+            self.z = logarize(axes[2]);
+
+            // self.vibration
+            //     .vibrate(self.session, (&self.x, &self.y, &self.z));
+        } else {
+            self.x = 0.0;
+            self.y = 0.0;
+            self.z = 0.0;
+        }
+    }
+
+    fn update_stick(&mut self) {
+        if let Some(right_ref_point) = &self.right_ref {
+            // Get current controller orientation relative to stored reference point
+            let diff = right_ref_point.rot.clone().inverse() * self.right.rot;
+            let angles = diff.euler_angles();
+            let config = &self.profile.right;
+            let (pitch, roll) = config.stick_angles(right_ref_point, &self.right);
+
+            self.pitch = config.normalise(3, pitch) * MAX_ANGLE;
+            self.roll = config.normalise(4, roll) * MAX_ANGLE;
+            self.yaw = config.normalise(5, -angles.1) * MAX_ANGLE;
+        } else {
+            self.pitch = 0.0;
+            self.roll = 0.0;
+            self.yaw = 0.0;
+        }
+    }
+
+    fn update_yoke(&mut self) {
+        if let (Some(left_ref_point), Some(right_ref_point)) = (&self.left_ref, &self.right_ref) {
+            let (pitch, roll, yaw) = yoke::deflection(
+                &self.profile.yoke,
+                [left_ref_point, right_ref_point],
+                [&self.left, &self.right],
+            );
+            self.pitch = pitch * MAX_ANGLE;
+            self.roll = roll * MAX_ANGLE;
+            self.yaw = yaw * MAX_ANGLE;
+        } else {
+            self.pitch = 0.0;
+            self.roll = 0.0;
+            self.yaw = 0.0;
+        }
+        self.x = 0.0;
+        self.y = 0.0;
+        self.z = 0.0;
+    }
+
+    fn update_wheel(&mut self) {
+        let hands = match (&self.left_ref, &self.right_ref) {
+            (Some(_), Some(_)) => Some([&self.left, &self.right]),
+            _ => None,
+        };
+        self.steering = self.wheel.update(&self.profile.wheel, hands);
+        self.pitch = 0.0;
+        self.roll = 0.0;
+        self.yaw = 0.0;
+        self.x = 0.0;
+        self.y = 0.0;
+        self.z = 0.0;
+    }
+
+    fn update_pointer(&mut self) {
+        let pointer = &self.profile.pointer;
+        let hand = pointer.hand();
+        let aim = match hand {
+            0 => pointer.aim(&self.left),
+            _ => pointer.aim(&self.right),
+        };
+        if let Some(cursor) = aim {
+            self.cursor = cursor;
+        }
+        if pointer.mouse {
+            mouse::move_to(self.cursor.0, self.cursor.1);
+            let clicking = self.triggers[hand] > 0.5;
+            if clicking != self.clicking {
+                mouse::set_left_button(clicking);
+                self.clicking = clicking;
+            }
+        }
+        self.pitch = 0.0;
+        self.roll = 0.0;
+        self.yaw = 0.0;
+        self.x = 0.0;
+        self.y = 0.0;
+        self.z = 0.0;
+    }
+
+    fn set_axes(&mut self) {
+        let mut deflection = [
+            self.x,
+            self.y,
+            self.z,
+            self.pitch / MAX_ANGLE,
+            self.roll / MAX_ANGLE,
+            self.yaw / MAX_ANGLE,
+        ];
+        for hand in 0..2 {
+            let gain = self.tracking.gain(&self.profile.tracking, hand);
+            for (value, &driven) in deflection.iter_mut().zip(self.hand_axes(hand).iter()) {
+                if driven {
+                    *value *= gain;
+                }
+            }
+        }
+        let held = self.held_axes();
+        let output = self.axes.update(&self.profile.axes, deflection, held);
+        let taken = self.profile.mode_axes(self.mode);
+        for (&axis, &value) in FLIGHT_AXES.iter().zip(output.iter()) {
+            if !taken.contains(&axis) {
+                self.set_axis(axis, value);
+            }
+        }
+        // Hand styles only apply in stick and throttle mode, so another mode can
+        // use the latched throttle's axis
+        if self.mode == Mode::StickThrottle {
+            for hand in 0..2 {
+                let (config, _, _) = self.hand(hand);
+                if config.style == Style::LatchedThrottle {
+                    let axis = config.latched_axis;
+                    self.set_axis_unit(axis, self.latched[hand]);
+                }
+            }
+        }
+        if self.mode == Mode::Wheel {
+            let wheel = &self.profile.wheel;
+            let (steering, throttle, brake) =
+                (wheel.steering_axis, wheel.throttle_axis, wheel.brake_axis);
+            self.set_axis(steering, self.steering);
+            self.set_axis_unit(throttle, self.triggers[1]);
+            self.set_axis_unit(brake, self.triggers[0]);
+        }
+        if self.mode == Mode::Pointer && !self.profile.pointer.mouse {
+            let (x_axis, y_axis) = (self.profile.pointer.x_axis, self.profile.pointer.y_axis);
+            self.set_axis(x_axis, self.cursor.0);
+            self.set_axis(y_axis, self.cursor.1);
+        }
+        self.flush();
+    }
+
     fn set_input(&mut self, input_state: &ovrInputState) {
         let buttons = input_state.Buttons as i32;
-        self.joystick.set_btn(1, buttons & ovrButton_A > 0);
-        self.joystick.set_btn(2, buttons & ovrButton_B > 0);
-        self.joystick.set_btn(3, buttons & ovrButton_X > 0);
-        self.joystick.set_btn(4, buttons & ovrButton_Y > 0);
-        self.joystick.set_btn(5, buttons & ovrButton_LThumb > 0);
-        self.joystick.set_btn(6, buttons & ovrButton_RThumb > 0);
-        self.joystick.set_btn(7, buttons & ovrButton_Enter > 0);
+        self.set_btn(1, buttons & ovrButton_A > 0);
+        self.set_btn(2, buttons & ovrButton_B > 0);
+        self.set_btn(3, buttons & ovrButton_X > 0);
+        self.set_btn(4, buttons & ovrButton_Y > 0);
+        self.set_btn(5, buttons & ovrButton_LThumb > 0);
+        self.set_btn(6, buttons & ovrButton_RThumb > 0);
+        self.set_btn(7, buttons & ovrButton_Enter > 0);
         if self.opts.triggerbuttons {
-            self.joystick.set_btn(8, input_state.IndexTrigger[0] > 0.5);
-            self.joystick.set_btn(9, input_state.IndexTrigger[1] > 0.5);
+            self.set_btn(8, input_state.IndexTrigger[0] > 0.5);
+            self.set_btn(9, input_state.IndexTrigger[1] > 0.5);
         } else {
-            self.joystick.set_axis(Axis::SL0, (input_state.IndexTrigger[0] * 32768.0) as i32);
-            self.joystick.set_axis(Axis::SL1, (input_state.IndexTrigger[1] * 32768.0) as i32);
+            self.set_axis_unit(Axis::SL0, input_state.IndexTrigger[0]);
+            self.set_axis_unit(Axis::SL1, input_state.IndexTrigger[1]);
         }
         for i in 0..2 {
             self.set_thumbstick(input_state.Thumbstick[i], i as u8 + 1);
@@ -247,10 +1027,10 @@ impl MainState {
                 // if pov = 1, use buttons 10-13 inclusive
                 // if pov = 2, use buttons 14-17 inclusive
                 // NEUTRAL position sets all buttons to 0
-                self.joystick.set_btn(10 + 4 * (pov - 1) + i as u8, i == direction as usize);
+                self.set_btn(10 + 4 * (pov - 1) + i as u8, i == direction as usize);
             }
         } else {
-            self.joystick.set_pov(pov, direction);
+            self.set_pov(pov, direction);
         }
     }
 
@@ -281,229 +1061,294 @@ pub fn logarize(n: f32) -> f32 {
     }
 }
 
-impl event::EventHandler for MainState {
-    fn update(&mut self, _ctx: &mut Context) -> GameResult {
+impl MainState {
+    // Reads the controllers and writes vJoy once. Returns false once ocujoy should quit.
+    fn tick(&mut self) -> bool {
+        if self.stop.load(Ordering::SeqCst) {
+            return false;
+        }
+        // Ticks that deliberately send nothing keep the watchdog quiet, so it
+        // only reports real stalls
+        let session = match self.supervise() {
+            Some(session) => session,
+            None => {
+                self.watchdog.suspend();
+                return true;
+            }
+        };
+        if !self.check_session(session) {
+            return false;
+        }
+        if !self.acquired || self.session.is_none() || self.paused {
+            self.watchdog.suspend();
+            return true;
+        }
         unsafe {
             let mut poses: [ovrPoseStatef; 2] = ::std::mem::zeroed();
             let device: [i32; 2] = [2, 4];
             let mut input_state: ovrInputState = ::std::mem::zeroed();
 
             // Get controller input state
-            ovr_GetInputState(
-                self.session,
+            let result = ovr_GetInputState(
+                session,
                 ovrControllerType_Touch,
                 &mut input_state as *mut ovrInputState,
             );
+            // Whatever went wrong, input_state can't be trusted. Reconnecting is
+            // the only way back from most failures, e.g. a lost display.
+            if !OVR_SUCCESS(result) {
+                self.lose_session();
+                return true;
+            }
 
             if self.opts.input {
                 self.set_input(&input_state);
             }
 
             let grips = input_state.HandTrigger;
-            ovr_GetDevicePoses(
-                self.session,
-                &device as *const i32,
-                2,
-                0.0,
-                poses.as_mut_ptr(),
+            ovr_GetDevicePoses(session, &device as *const i32, 2, 0.0, poses.as_mut_ptr());
+
+            // Lost hands stay where they were last tracked
+            let flags = ovr_GetTrackingState(session, 0.0, 0).HandStatusFlags;
+            let tracked = ovrStatus_OrientationTracked | ovrStatus_PositionTracked;
+            let was_tracked = [self.tracking.tracked(0), self.tracking.tracked(1)];
+            self.tracking.update(
+                &self.profile.tracking,
+                [
+                    flags[0] as i32 & tracked == tracked,
+                    flags[1] as i32 & tracked == tracked,
+                ],
             );
+            if self.tracking.tracked(0) {
+                self.left = Transform::new(poses[0].ThePose);
+            }
+            if self.tracking.tracked(1) {
+                self.right = Transform::new(poses[1].ThePose);
+            }
+            // The hand may have moved a long way while it was lost, so take
+            // hold again from where it came back
+            if self.tracking.tracked(0) && !was_tracked[0] && self.left_ref.is_some() {
+                self.left_ref = self.profile.left.grab(&self.left);
+            }
+            if self.tracking.tracked(1) && !was_tracked[1] && self.right_ref.is_some() {
+                self.right_ref = self.profile.right.grab(&self.right);
+            }
+
+            let buttons = input_state.Buttons as i32;
+            let pressed = buttons & !self.last_buttons;
+            self.last_buttons = buttons;
+            if self.calibration.is_some() {
+                self.calibrate([pressed & ovrButton_X > 0, pressed & ovrButton_A > 0]);
+                self.watchdog.suspend();
+                return true;
+            }
+            if buttons & SWITCH_MODE == SWITCH_MODE && pressed & SWITCH_MODE > 0 {
+                self.next_mode();
+            }
+            self.triggers = input_state.IndexTrigger;
 
-            self.left = Transform::new(poses[0].ThePose);
-            self.right = Transform::new(poses[1].ThePose);
+            for (hand, pose) in poses.iter().enumerate() {
+                if !self.tracking.tracked(hand) {
+                    if let Some(recogniser) = &mut self.gestures {
+                        recogniser.lost(hand);
+                    }
+                    continue;
+                }
+                let sample = Sample::new(pose);
+                let recorded = match &mut self.recorder {
+                    Some(recorder) => recorder.write(hand, &sample),
+                    None => Ok(()),
+                };
+                if let Err(e) = recorded {
+                    log!("Could not record gesture sample, stopped recording: {}", e);
+                    self.recorder = None;
+                }
+                if let Some(recogniser) = &mut self.gestures {
+                    for gesture in recogniser.update(hand, &sample) {
+                        log!("Gesture: {:?} ({})", gesture, ["left", "right"][hand]);
+                    }
+                }
+            }
+            let gesture_buttons = match &self.gestures {
+                Some(recogniser) => recogniser.buttons(ovr_GetTimeInSeconds()),
+                None => Vec::new(),
+            };
+            for (button, pressed) in gesture_buttons {
+                self.set_btn(button, pressed);
+            }
 
-            if grips[0] > 0.5 {
+            let gripping = [grips[0] > 0.5, grips[1] > 0.5];
+            self.cockpit
+                .update(&self.profile.controls, [&self.left, &self.right], gripping);
+            for hand in 0..2 {
+                let haptics = self.tracking.haptics(hand).or(self.cockpit.haptics(hand));
+                if let Some(amplitude) = haptics {
+                    set_haptics(session, hand, amplitude);
+                }
+            }
+            let controls: Vec<_> = self
+                .profile
+                .controls
+                .iter()
+                .zip(self.cockpit.values())
+                .map(|(control, value)| (control.button, control.axis, value))
+                .collect();
+            for (button, axis, value) in controls {
+                if button > 0 {
+                    self.set_btn(button, value > 0.5);
+                }
+                if let Some(axis) = axis {
+                    self.set_axis_unit(axis, value);
+                }
+            }
+
+            if grips[0] > 0.5 && !self.cockpit.holding(0) {
                 if let None = self.left_ref {
-                    self.left_ref = Some(self.left.clone());
+                    self.left_ref = self.profile.left.grab(&self.left);
                 }
             } else {
                 self.left_ref = None;
             }
 
-            if grips[1] > 0.5 {
+            if grips[1] > 0.5 && !self.cockpit.holding(1) {
                 if let None = self.right_ref {
-                    self.right_ref = Some(self.right.clone());
+                    self.right_ref = self.profile.right.grab(&self.right);
                 }
             } else {
                 self.right_ref = None;
             }
 
-            if let Some(left_ref_point) = &self.left_ref {
-                self.x = minmax(
-                    self.left.pos.coords.x - left_ref_point.pos.coords.x,
-                    -MAX_THROTTLE,
-                    MAX_THROTTLE,
-                ) / MAX_THROTTLE;
-                self.y = minmax(
-                    self.left.pos.coords.y - left_ref_point.pos.coords.y,
-                    -MAX_THROTTLE,
-                    MAX_THROTTLE,
-                ) / MAX_THROTTLE;
-                self.z = minmax(
-                    self.left.pos.coords.z - left_ref_point.pos.coords.z,
-                    -MAX_THROTTLE,
-                    MAX_THROTTLE,
-                ) / MAX_THROTTLE;
-
-                // self.vibration
-                //     .vibrate(self.session, (&self.x, &self.y, &self.z));
-
-                self.joystick
-                    .set_axis(
-                        Axis::X,
-                        (logarize(self.x) * RANGE / 2.0 + RANGE / 2.0) as i32,
-                    )
-                    .expect("Could not set axis");
-                self.joystick
-                    .set_axis(
-                        Axis::Y,
-                        (logarize(self.y) * RANGE / 2.0 + RANGE / 2.0) as i32,
-                    )
-                    .expect("Could not set axis");
-                self.joystick
-                    .set_axis(
-                        Axis::Z,
-                        (logarize(self.z) * RANGE / 2.0 + RANGE / 2.0) as i32,
-                    )
-                    .expect("Could not set axis");
-            } else {
-                self.joystick
-                    .set_axis(Axis::X, (RANGE / 2.0) as i32)
-                    .expect("Could not set axis");
-                self.joystick
-                    .set_axis(Axis::Y, (RANGE / 2.0) as i32)
-                    .expect("Could not set axis");
-                self.joystick
-                    .set_axis(Axis::Z, (RANGE / 2.0) as i32)
-                    .expect("Could not set axis");
-            }
-
-            // Flight stick
-            if let Some(right_ref_point) = &self.right_ref {
-                // Get current controller orientation relative to stored reference point
-                let diff = right_ref_point.rot.clone().inverse() * self.right.rot;
-                let angles = diff.euler_angles();
-
-                self.pitch = minmax(angles.0, -MAX_ANGLE, MAX_ANGLE);
-                self.roll = -minmax(angles.2, -MAX_ANGLE, MAX_ANGLE);
-                self.yaw = -minmax(angles.1, -MAX_ANGLE, MAX_ANGLE);
-
-                // Set vJoy joysticks
-                self.joystick
-                    .set_axis(
-                        Axis::RX,
-                        (self.pitch / MAX_ANGLE * RANGE / 2.0 + RANGE / 2.0) as i32,
-                    )
-                    .expect("Could not set axis");
-                self.joystick
-                    .set_axis(
-                        Axis::RY,
-                        (self.roll / MAX_ANGLE * RANGE / 2.0 + RANGE / 2.0) as i32,
-                    )
-                    .expect("Could not set axis");
-                self.joystick
-                    .set_axis(
-                        Axis::RZ,
-                        (self.yaw / MAX_ANGLE * RANGE / 2.0 + RANGE / 2.0) as i32,
-                    )
-                    .expect("Could not set axis");
-            } else {
-                self.joystick
-                    .set_axis(Axis::RX, (RANGE / 2.0) as i32)
-                    .expect("Could not set axis");
-                self.joystick
-                    .set_axis(Axis::RY, (RANGE / 2.0) as i32)
-                    .expect("Could not set axis");
-                self.joystick
-                    .set_axis(Axis::RZ, (RANGE / 2.0) as i32)
-                    .expect("Could not set axis");
-            }
-            Ok(())
-        }
-    }
-
-    fn draw(&mut self, ctx: &mut Context) -> GameResult {
-        graphics::clear(ctx, [0.0, 0.0, 0.0, 1.0].into());
-        let red_circle = graphics::Mesh::new_circle(
-            ctx,
-            graphics::DrawMode::fill(),
-            na::Point2::new(0.0, 0.0),
-            5.0,
-            2.0,
-            graphics::Color::new(1.0, 0.0, 0.0, 1.0),
-        )?;
-        let green_circle = graphics::Mesh::new_circle(
-            ctx,
-            graphics::DrawMode::fill(),
-            na::Point2::new(0.0, 0.0),
-            5.0,
-            2.0,
-            graphics::Color::new(0.0, 1.0, 0.0, 1.0),
-        )?;
-
-        for (i, axis) in [self.pitch, self.roll, self.yaw].iter().enumerate() {
-            let length = 30f32;
-            let p1 = na::Point2::new(i as f32 * 50.0 + 325.0, 350.0);
-            let p2 = p1 + na::Vector2::new(axis.cos(), axis.sin()) * length;
-            let line = graphics::Mesh::new_line(ctx, &[p1, p2], 2.0, ggez::graphics::WHITE)?;
-            graphics::draw(ctx, &line, graphics::DrawParam::default())?;
-        }
-
-        for (i, axis) in [self.x, self.y, self.z].iter().enumerate() {
-            let length = 30f32;
-            let p1 = na::Point2::new(i as f32 * 50.0 + 475.0, 350.0 - axis * 100.0);
-            let p2 = p1 + na::Vector2::new(length, 0.0);
-            let line = graphics::Mesh::new_line(ctx, &[p1, p2], 2.0, ggez::graphics::WHITE)?;
-            graphics::draw(ctx, &line, graphics::DrawParam::default())?;
-        }
-
-        graphics::draw(
-            ctx,
-            &green_circle,
-            (na::Point2::new(self.left.pos.x, self.left.pos.y),),
-        )?;
-        graphics::draw(
-            ctx,
-            &red_circle,
-            (na::Point2::new(self.right.pos.x, self.right.pos.y),),
-        )?;
-        graphics::present(ctx)?;
-        Ok(())
-    }
-    fn quit_event(&mut self, _ctx: &mut Context) -> bool {
-        unsafe {
-            println!("Quitting");
-            ovr_Destroy(self.session);
-            ovr_Shutdown();
+            if pressed & self.profile.trim_button.mask() > 0 {
+                self.trim();
+            }
+
+            match self.mode {
+                Mode::StickThrottle => self.update_stick_throttle(),
+                Mode::Yoke => self.update_yoke(),
+                Mode::Wheel => self.update_wheel(),
+                Mode::Pointer => self.update_pointer(),
+            }
+            self.set_axes();
+            true
         }
-        false
+    }
+
+    fn handle(&mut self, request: Request) {
+        match request {
+            Request::NextMode => self.next_mode(),
+            Request::Trim => self.trim(),
+            Request::CalibrateRanges if self.calibration.is_none() => {
+                let calibration = Calibration::ranges();
+                log!("{}", calibration.prompt(&self.profile));
+                self.calibration = Some(calibration);
+                self.centre_axes();
+            }
+            Request::CalibrateRanges => (),
+        }
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            left: [self.left.pos.x, self.left.pos.y],
+            right: [self.right.pos.x, self.right.pos.y],
+            pitch: self.pitch,
+            roll: self.roll,
+            yaw: self.yaw,
+            x: self.x,
+            y: self.y,
+            z: self.z,
+            mode: self.mode,
+            paused: self.paused,
+            prompt: self
+                .calibration
+                .as_ref()
+                .map(|calibration| calibration.prompt(&self.profile)),
+            tracked: [self.tracking.tracked(0), self.tracking.tracked(1)],
+            trims: self.profile.axes.trims(),
+            problems: self
+                .vjoy_error
+                .iter()
+                .chain(self.oculus_error.iter())
+                .map(|e| e.to_string())
+                .collect(),
+        }
+    }
+
+    // Runs the mapping at the profile's update rate until ocujoy should quit
+    fn run(mut self, requests: Receiver<Request>, snapshot: Arc<Mutex<Snapshot>>) {
+        let mut timer = Timer::new(self.profile.update_rate);
+        loop {
+            for request in requests.try_iter() {
+                self.handle(request);
+            }
+            if !self.tick() {
+                break;
+            }
+            *snapshot.lock().unwrap() = self.snapshot();
+            timer.wait();
+        }
+    }
+}
+
+// The raw ovrSession pointer is all that keeps MainState from being Send. It's
+// moved to the mapping thread straight out of `new`, while `session` is still
+// None, and from then on `supervise` connects, `tick` uses and `lose_session`
+// and `shutdown` disconnect the session on that thread alone. No copy of the
+// pointer is handed to any other thread, so LibOVR only ever sees it from one.
+unsafe impl Send for MainState {}
+
+// Covers Ctrl-C, panics and errors as well as closing the window
+impl Drop for MainState {
+    fn drop(&mut self) {
+        self.shutdown();
     }
 }
 
 fn main() -> GameResult {
     let args: Vec<String> = args().collect();
     let opts: Opts = Opts::parse();
+    let profile = Profile::load(&opts.profile).map_err(GameError::ConfigError)?;
+    if let Some(path) = &opts.log {
+        log::to_file(path)?;
+    }
+    if let Some(path) = &opts.replay_gestures {
+        let recording = BufReader::new(File::open(path)?);
+        for (time, hand, gesture) in gesture::replay(recording, profile.gestures.clone()) {
+            log!("{:.3}s: {:?} ({})", time, gesture, ["left", "right"][hand]);
+        }
+        return Ok(());
+    }
     let capture_input = match args.get(1) {
         Some(arg) if arg == "--input" => true,
         _ => false
     };
     unsafe {
-        let mut params: ovrInitParams = ::std::mem::zeroed();
-        params.Flags |= ovrInit_RequestVersion + 0x00000010;
-        params.RequestedMinorVersion = OVR_MINOR_VERSION;
-        ovr_try(|| ovr_Initialize(&params as *const _)).unwrap();
-        let mut session: ovrSession = ::std::mem::zeroed();
-        let mut luid: ovrGraphicsLuid = ::std::mem::zeroed();
-        ovr_try(|| ovr_Create(&mut session as *mut _, &mut luid as *mut _)).unwrap();
-        assert!(!session.is_null());
         let stop = Arc::new(AtomicBool::new(false));
         let stop_clone = stop.clone();
         set_handler(move || stop_clone.store(true, Ordering::SeqCst))
             .expect("Error setting SIGINT handler");
-        let cb = ggez::ContextBuilder::new("super_simple", "ggez");
-        let (ctx, event_loop) = &mut cb.build()?;
-        let mut joystick = Joystick::new(1);
-        joystick.acquire();
-        let state = &mut MainState::new(session, joystick, opts)?;
-        event::run(ctx, event_loop, state)
+        let mut context = match opts.headless {
+            true => None,
+            false => Some(ggez::ContextBuilder::new("super_simple", "ggez").build()?),
+        };
+        let state = MainState::new(Joystick::new(1), profile, opts, stop.clone())?;
+        let snapshot = Arc::new(Mutex::new(Snapshot::default()));
+        let (requests, receiver) = mpsc::channel();
+        let (ctx, event_loop) = match &mut context {
+            Some((ctx, event_loop)) => (ctx, event_loop),
+            None => {
+                state.run(receiver, snapshot);
+                return Ok(());
+            }
+        };
+        let mapping = {
+            let snapshot = snapshot.clone();
+            thread::spawn(move || state.run(receiver, snapshot))
+        };
+        let window = &mut Window::new(snapshot, requests, stop.clone());
+        let result = event::run(ctx, event_loop, window);
+        stop.store(true, Ordering::SeqCst);
+        let _ = mapping.join();
+        result
     }
 }
@@ -0,0 +1,241 @@
+use crate::cockpit::ControlKind;
+use crate::hand::offsets;
+use crate::profile::Profile;
+use crate::transform::Transform;
+
+// Steps through a calibration while the user is sat in VR. Each step is
+// confirmed by pressing X (left hand) or A (right hand).
+pub enum Calibration {
+    Controls {
+        control: usize,
+        travel: bool,
+    },
+    Anchors {
+        hand: usize,
+    },
+    // Extremes are measured from neutral, once it's been set
+    Ranges {
+        hand: usize,
+        neutral: Option<Transform>,
+        min: [f32; 6],
+        max: [f32; 6],
+    },
+}
+
+// Anything smaller than these wasn't tried, so the old range is kept
+// cm
+const MIN_TRAVEL: f32 = 1.0;
+// Radians
+const MIN_TURN: f32 = 0.05;
+
+impl Calibration {
+    pub fn controls() -> Calibration {
+        Calibration::Controls {
+            control: 0,
+            travel: false,
+        }
+    }
+
+    pub fn anchors() -> Calibration {
+        Calibration::Anchors { hand: 0 }
+    }
+
+    pub fn ranges() -> Calibration {
+        Calibration::Ranges {
+            hand: 0,
+            neutral: None,
+            min: [0.0; 6],
+            max: [0.0; 6],
+        }
+    }
+
+    pub fn prompt(&self, profile: &Profile) -> String {
+        match self {
+            Calibration::Controls { control, travel } => match profile.controls.get(*control) {
+                None => "There are no cockpit controls in the profile".to_owned(),
+                Some(c) if *travel => format!(
+                    "Move a controller to the far end of {}'s travel and press A or X",
+                    c.name
+                ),
+                Some(c) => format!("Move a controller to {} and press A or X", c.name),
+            },
+            Calibration::Anchors { hand: 0 } => {
+                "Hold your left hand where the throttle rests and press X".to_owned()
+            }
+            Calibration::Anchors { .. } => {
+                "Hold your right hand where the stick rests and press A".to_owned()
+            }
+            Calibration::Ranges { hand, neutral, .. } => {
+                let (name, button) = [("left", "X"), ("right", "A")][*hand];
+                match neutral {
+                    None => format!("Hold your {} hand at rest and press {}", name, button),
+                    Some(_) => format!(
+                        "Move and twist your {} hand as far as is comfortable every way, then press {}",
+                        name, button
+                    ),
+                }
+            }
+        }
+    }
+
+    // Returns true once the calibration is finished
+    pub fn update(
+        &mut self,
+        profile: &mut Profile,
+        hands: [&Transform; 2],
+        pressed: [bool; 2],
+    ) -> bool {
+        let pressed_hand = pressed.iter().position(|&p| p);
+        match self {
+            Calibration::Controls { control, travel } => {
+                if let (Some(hand), Some(c)) = (pressed_hand, profile.controls.get_mut(*control)) {
+                    let pos = hands[hand].pos;
+                    if *travel {
+                        c.travel = [
+                            pos.x - c.position[0],
+                            pos.y - c.position[1],
+                            pos.z - c.position[2],
+                        ];
+                        *control += 1;
+                        *travel = false;
+                    } else {
+                        c.position = [pos.x, pos.y, pos.z];
+                        if c.kind == ControlKind::Lever {
+                            *travel = true;
+                        } else {
+                            *control += 1;
+                        }
+                    }
+                }
+                *control >= profile.controls.len()
+            }
+            Calibration::Anchors { hand } => {
+                if pressed[*hand] {
+                    match *hand {
+                        0 => profile.left.set_anchor(hands[0]),
+                        _ => profile.right.set_anchor(hands[1]),
+                    }
+                    *hand += 1;
+                }
+                *hand > 1
+            }
+            Calibration::Ranges {
+                hand,
+                neutral,
+                min,
+                max,
+            } => {
+                if let Some(neutral) = neutral {
+                    let offsets = offsets(neutral, hands[*hand]);
+                    for i in 0..6 {
+                        min[i] = min[i].min(offsets[i]);
+                        max[i] = max[i].max(offsets[i]);
+                    }
+                }
+                if pressed[*hand] {
+                    if neutral.is_none() {
+                        *neutral = Some(hands[*hand].clone());
+                    } else {
+                        let config = match *hand {
+                            0 => &mut profile.left,
+                            _ => &mut profile.right,
+                        };
+                        for i in 0..6 {
+                            let least = if i < 3 { MIN_TRAVEL } else { MIN_TURN };
+                            if min[i] < -least {
+                                config.range_min[i] = min[i];
+                            }
+                            if max[i] > least {
+                                config.range_max[i] = max[i];
+                            }
+                        }
+                        *hand += 1;
+                        *neutral = None;
+                        *min = [0.0; 6];
+                        *max = [0.0; 6];
+                    }
+                }
+                *hand > 1
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cockpit::Control;
+    use crate::transform::at;
+    use ggez::nalgebra::UnitQuaternion;
+
+    fn control(name: &str, kind: ControlKind) -> Control {
+        Control {
+            name: name.to_owned(),
+            kind,
+            button: 1,
+            axis: None,
+            radius: 8.0,
+            position: [0.0; 3],
+            travel: [0.0; 3],
+        }
+    }
+
+    #[test]
+    fn places_controls_and_lever_travel() {
+        let mut profile = Profile::default();
+        profile.controls = vec![
+            control("gear", ControlKind::Lever),
+            control("lights", ControlKind::Toggle),
+        ];
+        let mut calibration = Calibration::controls();
+        let (left, right) = (at(10.0, 20.0, 30.0), at(10.0, 20.0, 45.0));
+        assert!(!calibration.update(&mut profile, [&left, &right], [true, false]));
+        assert!(!calibration.update(&mut profile, [&left, &right], [false, true]));
+        assert!(calibration.update(&mut profile, [&left, &right], [false, true]));
+        assert_eq!(profile.controls[0].position, [10.0, 20.0, 30.0]);
+        assert_eq!(profile.controls[0].travel, [0.0, 0.0, 15.0]);
+        assert_eq!(profile.controls[1].position, [10.0, 20.0, 45.0]);
+    }
+
+    #[test]
+    fn sets_each_hands_anchor_in_turn() {
+        let mut profile = Profile::default();
+        let mut calibration = Calibration::anchors();
+        let (left, right) = (at(-20.0, 80.0, 0.0), at(20.0, 80.0, 0.0));
+        // The right hand's button does nothing until the left anchor is set
+        assert!(!calibration.update(&mut profile, [&left, &right], [false, true]));
+        assert!(!calibration.update(&mut profile, [&left, &right], [true, false]));
+        assert!(calibration.update(&mut profile, [&left, &right], [false, true]));
+        assert_eq!(profile.left.anchor_position, [-20.0, 80.0, 0.0]);
+        assert_eq!(profile.right.anchor_position, [20.0, 80.0, 0.0]);
+    }
+
+    #[test]
+    fn records_ranges_from_neutral() {
+        let mut profile = Profile::default();
+        let old = profile.left.range_min;
+        let mut calibration = Calibration::ranges();
+        let still = at(0.0, 0.0, 0.0);
+        assert!(!calibration.update(&mut profile, [&at(0.0, 100.0, 0.0), &still], [true, false]));
+        calibration.update(
+            &mut profile,
+            [&at(0.0, 100.0, -25.0), &still],
+            [false, false],
+        );
+        calibration.update(
+            &mut profile,
+            [&at(0.0, 100.0, 10.0), &still],
+            [false, false],
+        );
+        let mut turned = at(0.5, 100.0, 0.0);
+        turned.rot = UnitQuaternion::from_euler_angles(0.3, 0.0, 0.0);
+        assert!(!calibration.update(&mut profile, [&turned, &still], [true, false]));
+
+        assert_eq!(profile.left.range_min[2], -25.0);
+        assert_eq!(profile.left.range_max[2], 10.0);
+        assert!((profile.left.range_max[3] - 0.3).abs() < 1e-5);
+        // Half a cm of X and no pitch down weren't really tried
+        assert_eq!(profile.left.range_max[0], old[0].abs());
+        assert_eq!(profile.left.range_min[3], old[3]);
+    }
+}
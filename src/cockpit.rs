@@ -0,0 +1,232 @@
+use ggez::nalgebra::{Point3, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::transform::Transform;
+use crate::vjoy::Axis;
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ControlKind {
+    // Held while grabbed or touched
+    Button,
+    // Flips each time it's grabbed or touched
+    Toggle,
+    // Dragged along its travel while grabbed, and stays where it's left
+    Lever,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Control {
+    pub name: String,
+    pub kind: ControlKind,
+    // vJoy button, 0 for none. Levers press it past halfway.
+    #[serde(default)]
+    pub button: u8,
+    #[serde(default)]
+    pub axis: Option<Axis>,
+    // cm from the hand to the control's handle to grab or touch it
+    #[serde(default = "default_radius")]
+    pub radius: f32,
+    // Set by `ocujoy place-controls`, in cm of tracking space
+    #[serde(default)]
+    pub position: [f32; 3],
+    // Levers only: from the resting end of the throw to the far end
+    #[serde(default)]
+    pub travel: [f32; 3],
+}
+
+fn default_radius() -> f32 {
+    8.0
+}
+
+impl Control {
+    fn handle(&self, value: f32) -> Point3<f32> {
+        let position = Point3::new(self.position[0], self.position[1], self.position[2]);
+        match self.kind {
+            ControlKind::Lever => position + self.travel_vector() * value,
+            _ => position,
+        }
+    }
+
+    fn travel_vector(&self) -> Vector3<f32> {
+        Vector3::new(self.travel[0], self.travel[1], self.travel[2])
+    }
+}
+
+#[derive(Default, Clone)]
+struct ControlState {
+    // 0..1 along a lever's travel, otherwise 0 or 1
+    value: f32,
+    // Grabbed or touched last tick
+    active: bool,
+    // Hand transform and value when the grab started
+    grab: Option<(Transform, f32)>,
+}
+
+pub struct Cockpit {
+    states: Vec<ControlState>,
+    gripping: [bool; 2],
+    holding: [Option<usize>; 2],
+    near: [bool; 2],
+    haptics: [Option<f32>; 2],
+}
+
+impl Cockpit {
+    pub fn new(controls: &[Control]) -> Cockpit {
+        Cockpit {
+            states: vec![ControlState::default(); controls.len()],
+            gripping: [false; 2],
+            holding: [None; 2],
+            near: [false; 2],
+            haptics: [None; 2],
+        }
+    }
+
+    pub fn holding(&self, hand: usize) -> bool {
+        self.holding[hand].is_some()
+    }
+
+    // Vibration amplitude to set on a hand this tick, if it changed
+    pub fn haptics(&self, hand: usize) -> Option<f32> {
+        self.haptics[hand]
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = f32> + '_ {
+        self.states.iter().map(|state| state.value)
+    }
+
+    fn nearest(&self, controls: &[Control], hand: &Transform) -> Option<(usize, f32)> {
+        let mut nearest: Option<(usize, f32)> = None;
+        for (i, (control, state)) in controls.iter().zip(self.states.iter()).enumerate() {
+            let distance = (control.handle(state.value) - hand.pos).norm();
+            if distance < control.radius && nearest.map_or(true, |(_, d)| distance < d) {
+                nearest = Some((i, distance));
+            }
+        }
+        nearest
+    }
+
+    pub fn update(&mut self, controls: &[Control], hands: [&Transform; 2], grips: [bool; 2]) {
+        let mut touching = [None; 2];
+        for hand in 0..2 {
+            let nearest = self.nearest(controls, hands[hand]);
+            let started = grips[hand] && !self.gripping[hand];
+            self.gripping[hand] = grips[hand];
+
+            if !grips[hand] {
+                self.holding[hand] = None;
+                touching[hand] = nearest.map(|(i, _)| i);
+            } else if started {
+                if let Some((i, _)) = nearest {
+                    self.holding[hand] = Some(i);
+                    let state = &mut self.states[i];
+                    state.grab = Some((hands[hand].clone(), state.value));
+                }
+            }
+
+            let was_near = self.near[hand];
+            self.near[hand] = !grips[hand] && nearest.is_some();
+            self.haptics[hand] = match nearest {
+                Some((i, distance)) if self.near[hand] => {
+                    Some(0.1 + 0.3 * (1.0 - distance / controls[i].radius))
+                }
+                _ if was_near => Some(0.0),
+                _ => None,
+            };
+        }
+
+        let holding = self.holding;
+        for (i, (control, state)) in controls.iter().zip(self.states.iter_mut()).enumerate() {
+            let holder = (0..2).find(|&hand| holding[hand] == Some(i));
+            let touched = touching.contains(&Some(i));
+            if holder.is_none() {
+                state.grab = None;
+            }
+
+            match control.kind {
+                ControlKind::Button => {
+                    state.value = if holder.is_some() || touched { 1.0 } else { 0.0 };
+                }
+                ControlKind::Toggle => {
+                    if (holder.is_some() || touched) && !state.active {
+                        state.value = 1.0 - state.value;
+                    }
+                }
+                ControlKind::Lever => {
+                    let travel = control.travel_vector();
+                    let length = travel.norm_squared();
+                    if let (Some(hand), Some((start, start_value))) = (holder, &state.grab) {
+                        if length > 0.0 {
+                            let offset = hands[hand].pos - start.pos;
+                            state.value =
+                                (start_value + offset.dot(&travel) / length).max(0.0).min(1.0);
+                        }
+                    }
+                }
+            }
+            state.active = holder.is_some() || touched;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transform::at;
+
+    fn control(kind: ControlKind) -> Control {
+        Control {
+            name: "gear".to_owned(),
+            kind,
+            button: 1,
+            axis: None,
+            radius: 8.0,
+            position: [0.0, 0.0, 0.0],
+            travel: [0.0, 0.0, 10.0],
+        }
+    }
+
+    fn value(cockpit: &Cockpit) -> f32 {
+        cockpit.values().next().unwrap()
+    }
+
+    #[test]
+    fn toggle_flips_on_touch_and_grab() {
+        let controls = [control(ControlKind::Toggle)];
+        let mut cockpit = Cockpit::new(&controls);
+        let (near, away) = (at(0.0, 0.0, 1.0), at(0.0, 50.0, 0.0));
+
+        cockpit.update(&controls, [&near, &away], [false, false]);
+        assert_eq!(value(&cockpit), 1.0);
+        // Resting a hand on it doesn't keep flipping it
+        cockpit.update(&controls, [&near, &away], [false, false]);
+        assert_eq!(value(&cockpit), 1.0);
+
+        cockpit.update(&controls, [&away, &away], [false, false]);
+        cockpit.update(&controls, [&near, &away], [true, false]);
+        assert_eq!(value(&cockpit), 0.0);
+        // Letting go while still touching it isn't a second flip
+        cockpit.update(&controls, [&near, &away], [false, false]);
+        assert_eq!(value(&cockpit), 0.0);
+    }
+
+    #[test]
+    fn lever_is_clamped_to_its_travel() {
+        let controls = [control(ControlKind::Lever)];
+        let mut cockpit = Cockpit::new(&controls);
+        let away = at(0.0, 50.0, 0.0);
+
+        cockpit.update(&controls, [&at(0.0, 0.0, 0.0), &away], [true, false]);
+        cockpit.update(&controls, [&at(0.0, 0.0, 5.0), &away], [true, false]);
+        assert_eq!(value(&cockpit), 0.5);
+        cockpit.update(&controls, [&at(0.0, 0.0, 25.0), &away], [true, false]);
+        assert_eq!(value(&cockpit), 1.0);
+        cockpit.update(&controls, [&at(0.0, 0.0, -5.0), &away], [true, false]);
+        assert_eq!(value(&cockpit), 0.0);
+
+        // It stays where it's left
+        cockpit.update(&controls, [&at(0.0, 0.0, 5.0), &away], [true, false]);
+        cockpit.update(&controls, [&away, &away], [false, false]);
+        assert_eq!(value(&cockpit), 0.5);
+    }
+}
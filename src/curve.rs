@@ -0,0 +1,41 @@
+// Applies a deadzone and a power curve to a value from -1 to 1. The deadzone
+// is a fraction of full deflection, and the rest of the range is stretched
+// to fill -1 to 1 again before the curve is applied.
+pub fn shape(value: f32, deadzone: f32, exponent: f32) -> f32 {
+    if deadzone >= 1.0 {
+        return 0.0;
+    }
+    let magnitude = ((value.abs() - deadzone) / (1.0 - deadzone))
+        .max(0.0)
+        .min(1.0);
+    magnitude.powf(exponent).copysign(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deadzone_swallows_small_deflection() {
+        assert_eq!(shape(0.04, 0.05, 1.0), 0.0);
+        assert_eq!(shape(-0.04, 0.05, 2.0), 0.0);
+    }
+
+    #[test]
+    fn full_deadzone_swallows_everything() {
+        assert_eq!(shape(1.0, 1.0, 2.0), 0.0);
+        assert_eq!(shape(-1.0, 1.5, 1.0), 0.0);
+    }
+
+    #[test]
+    fn full_deflection_is_kept() {
+        assert_eq!(shape(1.0, 0.1, 2.0), 1.0);
+        assert_eq!(shape(-1.0, 0.1, 3.0), -1.0);
+    }
+
+    #[test]
+    fn curve_keeps_sign() {
+        assert!((shape(-0.5, 0.0, 2.0) + 0.25).abs() < 1e-6);
+        assert!((shape(0.5, 0.0, 1.0) - 0.5).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,78 @@
+use std::fmt;
+
+use crate::vjoy::{Axis, UINT};
+
+// Everything that can go wrong talking to vJoy or the Oculus runtime
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    // vJoy isn't installed, or is turned off in Configure vJoy
+    VJoyDisabled,
+    // The device isn't set up in Configure vJoy
+    DeviceMissing(UINT),
+    // Another feeder has the device
+    DeviceOwned(UINT),
+    // vJoy refused the device for some other reason
+    DeviceUnavailable(UINT),
+    // ocujoy doesn't hold the device, e.g. it was never acquired or vJoy was reconfigured
+    NotAcquired(UINT),
+    FfbUnavailable(UINT),
+    // UpdateVJD refused the report
+    UpdateFailed(UINT),
+    ResetFailed,
+    AxisMissing(UINT, Axis),
+    ButtonOutOfRange(UINT, u8),
+    PovOutOfRange(UINT, u8),
+    // An ovrResult that wasn't a success, with the runtime's description of it
+    Ovr { code: i32, message: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::VJoyDisabled => write!(f, "vJoy is not enabled"),
+            Error::DeviceMissing(device) => write!(f, "vJoy device {} is not configured", device),
+            Error::DeviceOwned(device) => {
+                write!(f, "vJoy device {} is in use by another feeder", device)
+            }
+            Error::DeviceUnavailable(device) => {
+                write!(f, "Could not acquire vJoy device {}", device)
+            }
+            Error::NotAcquired(device) => write!(f, "vJoy device {} is not acquired", device),
+            Error::FfbUnavailable(device) => {
+                write!(f, "Could not start FFB on vJoy device {}", device)
+            }
+            Error::UpdateFailed(device) => write!(f, "Could not update vJoy device {}", device),
+            Error::ResetFailed => write!(f, "Could not reset vJoy devices"),
+            Error::AxisMissing(device, axis) => {
+                write!(f, "vJoy device {} has no {:?} axis", device, axis)
+            }
+            Error::ButtonOutOfRange(device, button) => {
+                write!(f, "vJoy device {} has no button {}", device, button)
+            }
+            Error::PovOutOfRange(device, pov) => {
+                write!(f, "vJoy device {} has no hat {}", device, pov)
+            }
+            Error::Ovr { code, message } => write!(f, "Oculus error {}: {}", code, message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describes_failures() {
+        assert_eq!(
+            Error::AxisMissing(1, Axis::RZ).to_string(),
+            "vJoy device 1 has no RZ axis"
+        );
+        let error = Error::Ovr {
+            code: -3001,
+            message: "No HMD".to_owned(),
+        };
+        assert_eq!(error.to_string(), "Oculus error -3001: No HMD");
+    }
+}
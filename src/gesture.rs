@@ -0,0 +1,356 @@
+use ggez::nalgebra as na;
+use ovr_sys::ovrPoseStatef;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::{self, BufRead, BufWriter, Write};
+use std::path::Path;
+
+use crate::transform::Transform;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Gesture {
+    Flick,
+    Punch,
+    Twist,
+    SwipeLeft,
+    SwipeRight,
+}
+
+const GESTURES: [Gesture; 5] = [
+    Gesture::Flick,
+    Gesture::Punch,
+    Gesture::Twist,
+    Gesture::SwipeLeft,
+    Gesture::SwipeRight,
+];
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct GestureConfig {
+    pub enabled: bool,
+    // Seconds a recognised gesture holds its button down
+    pub hold: f64,
+    // Seconds before the same gesture can fire again on the same hand
+    pub cooldown: f64,
+    // rad/s of wrist rotation, not counting twist
+    pub flick_speed: f32,
+    // cm/s along the direction the controller points
+    pub punch_speed: f32,
+    // Radians of twist about the pointing direction within twist_window seconds
+    pub twist_angle: f32,
+    pub twist_window: f64,
+    // cm/s sideways relative to the controller
+    pub swipe_speed: f32,
+    // vJoy buttons for [left, right]; 0 leaves the gesture unmapped
+    pub flick_buttons: [u8; 2],
+    pub punch_buttons: [u8; 2],
+    pub twist_buttons: [u8; 2],
+    pub swipe_left_buttons: [u8; 2],
+    pub swipe_right_buttons: [u8; 2],
+}
+
+impl Default for GestureConfig {
+    fn default() -> GestureConfig {
+        GestureConfig {
+            enabled: false,
+            hold: 0.1,
+            cooldown: 0.5,
+            flick_speed: 12.0,
+            punch_speed: 250.0,
+            twist_angle: PI / 2.0,
+            twist_window: 0.6,
+            swipe_speed: 200.0,
+            flick_buttons: [18, 19],
+            punch_buttons: [20, 21],
+            twist_buttons: [22, 23],
+            swipe_left_buttons: [24, 25],
+            swipe_right_buttons: [26, 27],
+        }
+    }
+}
+
+impl GestureConfig {
+    fn button(&self, gesture: Gesture, hand: usize) -> u8 {
+        match gesture {
+            Gesture::Flick => self.flick_buttons[hand],
+            Gesture::Punch => self.punch_buttons[hand],
+            Gesture::Twist => self.twist_buttons[hand],
+            Gesture::SwipeLeft => self.swipe_left_buttons[hand],
+            Gesture::SwipeRight => self.swipe_right_buttons[hand],
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub time: f64,
+    pub rot: na::UnitQuaternion<f32>,
+    // cm/s, to match Transform
+    pub linear_velocity: na::Vector3<f32>,
+    // rad/s
+    pub angular_velocity: na::Vector3<f32>,
+}
+
+impl Sample {
+    pub fn new(state: &ovrPoseStatef) -> Sample {
+        Sample {
+            time: state.TimeInSeconds,
+            rot: Transform::new(state.ThePose).rot,
+            linear_velocity: na::Vector3::new(
+                state.LinearVelocity.x,
+                state.LinearVelocity.y,
+                state.LinearVelocity.z,
+            ) * 100.0,
+            angular_velocity: na::Vector3::new(
+                state.AngularVelocity.x,
+                state.AngularVelocity.y,
+                state.AngularVelocity.z,
+            ),
+        }
+    }
+
+    fn to_csv(&self, hand: usize) -> String {
+        let q = self.rot.quaternion().coords;
+        let v = self.linear_velocity;
+        let w = self.angular_velocity;
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{}",
+            hand, self.time, q.w, q.x, q.y, q.z, v.x, v.y, v.z, w.x, w.y, w.z
+        )
+    }
+
+    fn from_csv(line: &str) -> Option<(usize, Sample)> {
+        let fields: Vec<&str> = line.trim().split(',').collect();
+        if fields.len() != 12 {
+            return None;
+        }
+        let hand: usize = fields[0].parse().ok()?;
+        if hand > 1 {
+            return None;
+        }
+        let time: f64 = fields[1].parse().ok()?;
+        let mut values = [0f32; 10];
+        for (value, field) in values.iter_mut().zip(&fields[2..]) {
+            *value = field.parse().ok()?;
+        }
+        Some((
+            hand,
+            Sample {
+                time,
+                rot: na::UnitQuaternion::new_normalize(na::Quaternion::new(
+                    values[0], values[1], values[2], values[3],
+                )),
+                linear_velocity: na::Vector3::new(values[4], values[5], values[6]),
+                angular_velocity: na::Vector3::new(values[7], values[8], values[9]),
+            },
+        ))
+    }
+}
+
+#[derive(Default)]
+struct HandState {
+    last_time: Option<f64>,
+    // (time, radians twisted since the previous sample)
+    twist: VecDeque<(f64, f32)>,
+    last_fired: [Option<f64>; 5],
+    pressed_until: [f64; 5],
+}
+
+pub struct Recogniser {
+    config: GestureConfig,
+    hands: [HandState; 2],
+}
+
+impl Recogniser {
+    pub fn new(config: GestureConfig) -> Recogniser {
+        Recogniser {
+            config,
+            hands: [HandState::default(), HandState::default()],
+        }
+    }
+
+    // Forgets a hand's motion so far, so the gap while it isn't tracked
+    // doesn't count as one long sample when it comes back
+    pub fn lost(&mut self, hand: usize) {
+        let state = &mut self.hands[hand];
+        state.last_time = None;
+        state.twist.clear();
+    }
+
+    pub fn update(&mut self, hand: usize, sample: &Sample) -> Vec<Gesture> {
+        let config = &self.config;
+        let state = &mut self.hands[hand];
+
+        let forward = sample.rot * na::Vector3::new(0.0, 0.0, -1.0);
+        let right = sample.rot * na::Vector3::new(1.0, 0.0, 0.0);
+
+        let twist_speed = sample.angular_velocity.dot(&forward);
+        let flick_speed = (sample.angular_velocity - forward * twist_speed).norm();
+        let forward_speed = sample.linear_velocity.dot(&forward);
+        let sideways_speed = sample.linear_velocity.dot(&right);
+
+        let dt = match state.last_time {
+            Some(last) => (sample.time - last).max(0.0),
+            None => 0.0,
+        };
+        state.last_time = Some(sample.time);
+        state.twist.push_back((sample.time, twist_speed * dt as f32));
+        while let Some(&(time, _)) = state.twist.front() {
+            if sample.time - time > config.twist_window {
+                state.twist.pop_front();
+            } else {
+                break;
+            }
+        }
+        let twist: f32 = state.twist.iter().map(|&(_, angle)| angle).sum();
+
+        let mut candidates = Vec::new();
+        if flick_speed > config.flick_speed {
+            candidates.push(Gesture::Flick);
+        }
+        if forward_speed > config.punch_speed && forward_speed > sideways_speed.abs() {
+            candidates.push(Gesture::Punch);
+        }
+        if twist.abs() > config.twist_angle {
+            candidates.push(Gesture::Twist);
+        }
+        if sideways_speed.abs() > config.swipe_speed && sideways_speed.abs() > forward_speed.abs() {
+            if sideways_speed > 0.0 {
+                candidates.push(Gesture::SwipeRight);
+            } else {
+                candidates.push(Gesture::SwipeLeft);
+            }
+        }
+
+        let mut fired = Vec::new();
+        for gesture in candidates {
+            let i = gesture as usize;
+            if let Some(last) = state.last_fired[i] {
+                if sample.time - last < config.cooldown {
+                    continue;
+                }
+            }
+            state.last_fired[i] = Some(sample.time);
+            state.pressed_until[i] = sample.time + config.hold;
+            if gesture == Gesture::Twist {
+                state.twist.clear();
+            }
+            fired.push(gesture);
+        }
+        fired
+    }
+
+    pub fn buttons(&self, time: f64) -> Vec<(u8, bool)> {
+        let mut buttons = Vec::new();
+        for (hand, state) in self.hands.iter().enumerate() {
+            for &gesture in GESTURES.iter() {
+                let button = self.config.button(gesture, hand);
+                if button > 0 {
+                    buttons.push((button, time < state.pressed_until[gesture as usize]));
+                }
+            }
+        }
+        buttons
+    }
+}
+
+pub struct Recorder {
+    out: BufWriter<File>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> io::Result<Recorder> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "hand,time,qw,qx,qy,qz,vx,vy,vz,wx,wy,wz")?;
+        Ok(Recorder { out })
+    }
+
+    pub fn write(&mut self, hand: usize, sample: &Sample) -> io::Result<()> {
+        writeln!(self.out, "{}", sample.to_csv(hand))
+    }
+}
+
+// Runs a recording made with --record-gestures back through the recogniser
+pub fn replay<R: BufRead>(input: R, config: GestureConfig) -> Vec<(f64, usize, Gesture)> {
+    let mut recogniser = Recogniser::new(config);
+    let mut recognised = Vec::new();
+    for line in input.lines().filter_map(Result::ok) {
+        if let Some((hand, sample)) = Sample::from_csv(&line) {
+            for gesture in recogniser.update(hand, &sample) {
+                recognised.push((sample.time, hand, gesture));
+            }
+        }
+    }
+    recognised
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recording(linear: [f32; 3], angular: [f32; 3], seconds: f64) -> String {
+        let mut lines = vec!["hand,time,qw,qx,qy,qz,vx,vy,vz,wx,wy,wz".to_owned()];
+        let mut time = 0.0;
+        while time < seconds {
+            let sample = Sample {
+                time,
+                rot: na::UnitQuaternion::identity(),
+                linear_velocity: na::Vector3::new(linear[0], linear[1], linear[2]),
+                angular_velocity: na::Vector3::new(angular[0], angular[1], angular[2]),
+            };
+            lines.push(sample.to_csv(1));
+            time += 0.01;
+        }
+        lines.join("\n")
+    }
+
+    fn gestures(linear: [f32; 3], angular: [f32; 3], seconds: f64) -> Vec<Gesture> {
+        let input = recording(linear, angular, seconds);
+        replay(input.as_bytes(), GestureConfig::default())
+            .into_iter()
+            .map(|(_, _, gesture)| gesture)
+            .collect()
+    }
+
+    #[test]
+    fn still_hand_is_not_a_gesture() {
+        assert!(gestures([0.0, 0.0, 0.0], [0.0, 0.0, 0.0], 1.0).is_empty());
+    }
+
+    #[test]
+    fn recognises_punch_once_per_cooldown() {
+        assert_eq!(gestures([0.0, 0.0, -300.0], [0.0, 0.0, 0.0], 0.3), vec![Gesture::Punch]);
+    }
+
+    #[test]
+    fn recognises_swipes() {
+        assert_eq!(gestures([-300.0, 0.0, 0.0], [0.0, 0.0, 0.0], 0.1), vec![Gesture::SwipeLeft]);
+        assert_eq!(gestures([300.0, 0.0, 0.0], [0.0, 0.0, 0.0], 0.1), vec![Gesture::SwipeRight]);
+    }
+
+    #[test]
+    fn losing_a_hand_forgets_its_motion() {
+        let sample = |time| Sample {
+            time,
+            rot: na::UnitQuaternion::identity(),
+            linear_velocity: na::Vector3::new(0.0, 0.0, 0.0),
+            angular_velocity: na::Vector3::new(0.0, 0.0, 8.0),
+        };
+        let mut recogniser = Recogniser::new(GestureConfig::default());
+        assert!(recogniser.update(1, &sample(0.0)).is_empty());
+        recogniser.lost(1);
+        assert!(recogniser.update(1, &sample(5.0)).is_empty());
+    }
+
+    #[test]
+    fn recognises_twist_but_not_as_flick() {
+        assert_eq!(gestures([0.0, 0.0, 0.0], [0.0, 0.0, 8.0], 0.4), vec![Gesture::Twist]);
+    }
+
+    #[test]
+    fn recognises_flick() {
+        assert_eq!(gestures([0.0, 0.0, 0.0], [15.0, 0.0, 0.0], 0.05), vec![Gesture::Flick]);
+    }
+}
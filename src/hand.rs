@@ -0,0 +1,356 @@
+use ggez::nalgebra::{self as na, Point3, Vector3};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+use crate::curve;
+use crate::minmax;
+use crate::transform::{elevation, Transform};
+use crate::vjoy::Axis;
+use crate::{MAX_ANGLE, MAX_THROTTLE};
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Reference {
+    // Deflection is measured from wherever the grip started
+    Grab,
+    // Deflection is measured from a fixed neutral, set with `ocujoy set-anchors`
+    Anchored,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Style {
+    // Left hand is the throttle on X/Y/Z, right hand is the stick on rX/rY/rZ
+    Standard,
+    // A lever hinged at `hinge`, its angle on Y
+    Collective,
+    // A long stick pivoting `cyclic_pivot` below the hand, on rX/rY
+    Cyclic,
+    // Translation on X/Y/Z and rotation on rX/rY/rZ, all from the one hand
+    SixDof,
+    // Pushing forward or pulling back moves a throttle on `latched_axis`,
+    // which stays where it is when the grip is let go
+    LatchedThrottle,
+    // Drives no axes, leaving the hand for buttons
+    Unused,
+}
+
+impl Style {
+    // Which of X, Y, Z, rX, rY and rZ the style drives from the given hand
+    pub fn axes(self, hand: usize) -> [bool; 6] {
+        match self {
+            Style::Standard if hand == 0 => [true, true, true, false, false, false],
+            Style::Standard => [false, false, false, true, true, true],
+            Style::Collective => [false, true, false, false, false, false],
+            Style::Cyclic => [false, false, false, true, true, false],
+            Style::SixDof => [true; 6],
+            Style::LatchedThrottle | Style::Unused => [false; 6],
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct HandConfig {
+    pub style: Style,
+    pub reference: Reference,
+    // Anchored only: cm from the anchor the grip has to start within, 0 for anywhere
+    pub anchor_radius: f32,
+    pub anchor_position: [f32; 3],
+    // w, x, y, z
+    pub anchor_rotation: [f32; 4],
+    // Stick hand only: cm below the hand that the stick pivots around, 0 to
+    // pivot at the wrist
+    pub pivot: f32,
+    // Collective only: cm from the hand to the lever's hinge when the grip starts
+    pub hinge: [f32; 3],
+    // Collective only: radians the lever moves for full deflection
+    pub collective_angle: f32,
+    pub cyclic_pivot: f32,
+    pub cyclic_angle: f32,
+    // SixDof only: for X, Y, Z, pitch, roll and yaw
+    pub deadzone: [f32; 6],
+    pub curve: [f32; 6],
+    // Taken over from the flight axes in stick and throttle mode
+    pub latched_axis: Axis,
+    // How far the hand comfortably moves each way from neutral, for X, Y, Z
+    // (cm), pitch, roll and yaw (radians). Set by `ocujoy calibrate-ranges`.
+    pub range_min: [f32; 6],
+    pub range_max: [f32; 6],
+}
+
+impl Default for HandConfig {
+    fn default() -> HandConfig {
+        HandConfig {
+            style: Style::Standard,
+            reference: Reference::Grab,
+            anchor_radius: 15.0,
+            anchor_position: [0.0, 0.0, 0.0],
+            anchor_rotation: [1.0, 0.0, 0.0, 0.0],
+            pivot: 0.0,
+            hinge: [0.0, 0.0, 40.0],
+            collective_angle: PI / 6.0,
+            cyclic_pivot: 50.0,
+            cyclic_angle: PI / 12.0,
+            deadzone: [0.05; 6],
+            curve: [2.0, 2.0, 2.0, 1.0, 1.0, 1.0],
+            latched_axis: Axis::Z,
+            range_min: [
+                -MAX_THROTTLE,
+                -MAX_THROTTLE,
+                -MAX_THROTTLE,
+                -MAX_ANGLE,
+                -MAX_ANGLE,
+                -MAX_ANGLE,
+            ],
+            range_max: [
+                MAX_THROTTLE,
+                MAX_THROTTLE,
+                MAX_THROTTLE,
+                MAX_ANGLE,
+                MAX_ANGLE,
+                MAX_ANGLE,
+            ],
+        }
+    }
+}
+
+impl HandConfig {
+    pub fn anchor(&self) -> Transform {
+        let p = self.anchor_position;
+        let r = self.anchor_rotation;
+        Transform {
+            pos: Point3::new(p[0], p[1], p[2]),
+            rot: na::UnitQuaternion::new_normalize(na::Quaternion::new(r[0], r[1], r[2], r[3])),
+        }
+    }
+
+    pub fn set_anchor(&mut self, hand: &Transform) {
+        let q = hand.rot.quaternion().coords;
+        self.anchor_position = [hand.pos.x, hand.pos.y, hand.pos.z];
+        self.anchor_rotation = [q.w, q.x, q.y, q.z];
+    }
+
+    // Stick pitch and roll in radians: the wrist's tilt, plus the tilt of the
+    // line from the pivot up to the hand if there is a pivot
+    pub fn stick_angles(&self, reference: &Transform, hand: &Transform) -> (f32, f32) {
+        let angles = (reference.rot.inverse() * hand.rot).euler_angles();
+        let (pitch, roll) = (angles.0, -angles.2);
+        if self.pivot <= 0.0 {
+            return (pitch, roll);
+        }
+        let (arm_pitch, arm_roll) = pivot_angles(self.pivot, reference, hand);
+        (pitch + arm_pitch, roll + arm_roll)
+    }
+
+    // Collective from -1 to 1, from how far the lever has swung up or down about its hinge
+    pub fn collective(&self, reference: &Transform, hand: &Transform) -> f32 {
+        let hinge = reference.pos + Vector3::new(self.hinge[0], self.hinge[1], self.hinge[2]);
+        let angle = elevation(&(hand.pos - hinge)) - elevation(&(reference.pos - hinge));
+        minmax(angle / self.collective_angle, -1.0, 1.0)
+    }
+
+    // Cyclic pitch and roll from -1 to 1
+    pub fn cyclic(&self, reference: &Transform, hand: &Transform) -> (f32, f32) {
+        let (pitch, roll) = pivot_angles(self.cyclic_pivot, reference, hand);
+        (
+            minmax(pitch / self.cyclic_angle, -1.0, 1.0),
+            minmax(roll / self.cyclic_angle, -1.0, 1.0),
+        )
+    }
+
+    // Scales one of the offsets to -1..1 by the range in its direction
+    pub fn normalise(&self, axis: usize, offset: f32) -> f32 {
+        let limit = match offset < 0.0 {
+            true => -self.range_min[axis],
+            false => self.range_max[axis],
+        };
+        if limit <= 0.0 {
+            return 0.0;
+        }
+        minmax(offset / limit, -1.0, 1.0)
+    }
+
+    // X, Y, Z, pitch, roll and yaw from -1 to 1
+    pub fn normalised(&self, reference: &Transform, hand: &Transform) -> [f32; 6] {
+        let mut axes = offsets(reference, hand);
+        for (i, axis) in axes.iter_mut().enumerate() {
+            *axis = self.normalise(i, *axis);
+        }
+        axes
+    }
+
+    // X, Y, Z, pitch, roll and yaw from -1 to 1, each through its own deadzone and curve
+    pub fn six_dof(&self, reference: &Transform, hand: &Transform) -> [f32; 6] {
+        let mut axes = self.normalised(reference, hand);
+        for (i, axis) in axes.iter_mut().enumerate() {
+            *axis = curve::shape(*axis, self.deadzone[i], self.curve[i]);
+        }
+        axes
+    }
+
+    // The reference to measure deflection from while gripping, if the grip
+    // should take hold at all
+    pub fn grab(&self, hand: &Transform) -> Option<Transform> {
+        match self.reference {
+            Reference::Grab => Some(hand.clone()),
+            Reference::Anchored => {
+                let anchor = self.anchor();
+                let distance = (hand.pos - anchor.pos).norm();
+                if self.anchor_radius <= 0.0 || distance < self.anchor_radius {
+                    Some(anchor)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    // The reference to carry on from after trimming mid-grip. The trim already
+    // holds the deflection so far, so it's where the hand is now even when
+    // anchored, or that deflection would be counted twice.
+    pub fn regrab(&self, hand: &Transform) -> Transform {
+        hand.clone()
+    }
+}
+
+// X, Y, Z in cm and pitch, roll, yaw in radians of the hand from the reference
+pub fn offsets(reference: &Transform, hand: &Transform) -> [f32; 6] {
+    let offset = hand.pos - reference.pos;
+    let angles = (reference.rot.inverse() * hand.rot).euler_angles();
+    [offset.x, offset.y, offset.z, angles.0, -angles.2, -angles.1]
+}
+
+// The pivot sits straight below the reference, so the stick starts upright
+fn pivot_angles(length: f32, reference: &Transform, hand: &Transform) -> (f32, f32) {
+    let pivot = reference.pos - Vector3::new(0.0, length, 0.0);
+    let stick = hand.pos - pivot;
+    (stick.z.atan2(stick.y), stick.x.atan2(stick.y))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transform::at;
+
+    fn config() -> HandConfig {
+        let mut config = HandConfig::default();
+        config.range_min[2] = -10.0;
+        config.range_max[2] = 25.0;
+        config
+    }
+
+    #[test]
+    fn ranges_are_per_direction() {
+        let config = config();
+        assert_eq!(config.normalise(2, -5.0), -0.5);
+        assert_eq!(config.normalise(2, 5.0), 0.2);
+        assert_eq!(config.normalise(2, 0.0), 0.0);
+    }
+
+    #[test]
+    fn offsets_past_the_range_are_clamped() {
+        let config = config();
+        assert_eq!(config.normalise(2, -30.0), -1.0);
+        assert_eq!(config.normalise(2, 30.0), 1.0);
+    }
+
+    #[test]
+    fn regrabbing_after_a_trim_ignores_the_anchor() {
+        let mut config = config();
+        config.reference = Reference::Anchored;
+        let hand = at(0.0, 0.0, -5.0);
+        let anchor = config.grab(&hand).unwrap();
+        assert_eq!(config.normalised(&anchor, &hand)[2], -0.5);
+        let reference = config.regrab(&hand);
+        assert_eq!(config.normalised(&reference, &hand), [0.0; 6]);
+    }
+
+    #[test]
+    fn empty_range_gives_nothing() {
+        let mut config = config();
+        config.range_max[0] = 0.0;
+        assert_eq!(config.normalise(0, 5.0), 0.0);
+    }
+
+    fn moved(pos: [f32; 3], pitch: f32, roll: f32) -> Transform {
+        Transform {
+            pos: Point3::new(pos[0], pos[1], pos[2]),
+            rot: na::UnitQuaternion::from_euler_angles(pitch, 0.0, -roll),
+        }
+    }
+
+    fn assert_angles(actual: (f32, f32), expected: (f32, f32)) {
+        assert!(
+            (actual.0 - expected.0).abs() < 1e-5 && (actual.1 - expected.1).abs() < 1e-5,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn stick_follows_the_wrist() {
+        let mut config = HandConfig::default();
+        let hand = moved([0.0, 0.0, 0.0], 0.2, 0.1);
+        assert_angles(
+            config.stick_angles(&Transform::default(), &hand),
+            (0.2, 0.1),
+        );
+        config.pivot = 30.0;
+        assert_angles(
+            config.stick_angles(&Transform::default(), &hand),
+            (0.2, 0.1),
+        );
+    }
+
+    #[test]
+    fn stick_follows_the_arm_about_the_pivot() {
+        let mut config = HandConfig::default();
+        let hand = moved([10.0, 0.0, 10.0], 0.0, 0.0);
+        assert_angles(
+            config.stick_angles(&Transform::default(), &hand),
+            (0.0, 0.0),
+        );
+        config.pivot = 30.0;
+        let arm = (10.0f32).atan2(30.0);
+        assert_angles(
+            config.stick_angles(&Transform::default(), &hand),
+            (arm, arm),
+        );
+    }
+
+    #[test]
+    fn stick_adds_wrist_to_arm() {
+        let mut config = HandConfig::default();
+        config.pivot = 30.0;
+        let hand = moved([-10.0, 0.0, 10.0], 0.2, 0.1);
+        let arm = (10.0f32).atan2(30.0);
+        assert_angles(
+            config.stick_angles(&Transform::default(), &hand),
+            (0.2 + arm, 0.1 - arm),
+        );
+    }
+
+    #[test]
+    fn collective_follows_the_lever_angle() {
+        let config = HandConfig::default();
+        let reference = Transform::default();
+        // Half of the default 30 degree swing, 40 cm from the hinge
+        let raised = moved([0.0, 40.0 * (PI / 12.0).tan(), 0.0], 0.0, 0.0);
+        assert_angles((config.collective(&reference, &raised), 0.0), (0.5, 0.0));
+        let lowered = moved([0.0, -40.0, 0.0], 0.0, 0.0);
+        assert_eq!(config.collective(&reference, &lowered), -1.0);
+    }
+
+    #[test]
+    fn cyclic_follows_the_stick_angle() {
+        let config = HandConfig::default();
+        let reference = Transform::default();
+        // Half of the default 15 degree tilt, 50 cm above the pivot
+        let right = moved([50.0 * (PI / 24.0).tan(), 0.0, 0.0], 0.0, 0.0);
+        assert_angles(config.cyclic(&reference, &right), (0.0, 0.5));
+        let pulled = moved([0.0, 0.0, 50.0], 0.0, 0.0);
+        assert_angles(config.cyclic(&reference, &pulled), (1.0, 0.0));
+    }
+}
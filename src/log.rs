@@ -0,0 +1,88 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+static FILE: Mutex<Option<File>> = Mutex::new(None);
+
+// Prints a line, and writes it to the log file too if there is one
+macro_rules! log {
+    ($($arg:tt)*) => ($crate::log::write(&format!($($arg)*)));
+}
+
+// Also append everything logged to a file
+pub fn to_file(path: &Path) -> io::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    *FILE.lock().unwrap() = Some(file);
+    Ok(())
+}
+
+pub fn write(message: &str) {
+    println!("{}", message);
+    if let Some(file) = FILE.lock().unwrap().as_mut() {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let _ = writeln!(file, "[{}] {}", timestamp(time), message);
+    }
+}
+
+// UTC date and time to the millisecond, e.g. 2024-03-01 18:04:05.250
+fn timestamp(since_epoch: Duration) -> String {
+    let seconds = since_epoch.as_secs();
+    let (year, month, day) = civil_date((seconds / 86400) as i64);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03}",
+        year,
+        month,
+        day,
+        seconds / 3600 % 24,
+        seconds / 60 % 60,
+        seconds % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+// Year, month and day of a count of days since 1970-01-01, in the Gregorian
+// calendar. Counts in 400 year eras starting on the 1st of March, so leap days
+// fall at the end of each year.
+fn civil_date(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_from_march + 2) / 5 + 1) as u32;
+    let month = match month_from_march < 10 {
+        true => month_from_march + 3,
+        false => month_from_march - 9,
+    } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps_are_utc_dates_and_times() {
+        assert_eq!(timestamp(Duration::from_secs(0)), "1970-01-01 00:00:00.000");
+        assert_eq!(
+            timestamp(Duration::from_millis(1709316245250)),
+            "2024-03-01 18:04:05.250"
+        );
+        // A leap day, and the last millisecond of a year
+        assert_eq!(
+            timestamp(Duration::from_secs(951782400)),
+            "2000-02-29 00:00:00.000"
+        );
+        assert_eq!(
+            timestamp(Duration::from_millis(1704067199999)),
+            "2023-12-31 23:59:59.999"
+        );
+    }
+}
@@ -0,0 +1,208 @@
+use std::fmt;
+
+use crate::error::Error;
+use crate::profile::Mode;
+use crate::vjoy::{Axis, Capabilities, UINT};
+
+// Something ocujoy writes to on the vJoy device
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Output {
+    Axis(Axis),
+    Button(u8),
+    // Discrete hat
+    Pov(u8),
+}
+
+impl fmt::Display for Output {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Output::Axis(axis) => write!(f, "axis {:?}", axis),
+            Output::Button(button) => write!(f, "button {}", button),
+            Output::Pov(pov) => write!(f, "hat {}", pov),
+        }
+    }
+}
+
+// An output and what it's for, e.g. "left thumbstick"
+pub struct Mapping {
+    pub output: Output,
+    pub purpose: String,
+    // The only mode it's written in, or None if it's written in every mode
+    pub mode: Option<Mode>,
+    // Modes that write something else to the output instead
+    pub except: Vec<Mode>,
+}
+
+impl Mapping {
+    pub fn axis(axis: Axis, purpose: &str) -> Mapping {
+        Mapping {
+            output: Output::Axis(axis),
+            purpose: purpose.to_owned(),
+            mode: None,
+            except: Vec::new(),
+        }
+    }
+
+    pub fn button(button: u8, purpose: &str) -> Mapping {
+        Mapping {
+            output: Output::Button(button),
+            purpose: purpose.to_owned(),
+            mode: None,
+            except: Vec::new(),
+        }
+    }
+
+    pub fn pov(pov: u8, purpose: &str) -> Mapping {
+        Mapping {
+            output: Output::Pov(pov),
+            purpose: purpose.to_owned(),
+            mode: None,
+            except: Vec::new(),
+        }
+    }
+
+    pub fn only_in(mut self, mode: Mode) -> Mapping {
+        self.mode = Some(mode);
+        self
+    }
+
+    pub fn except_in(mut self, mode: Mode) -> Mapping {
+        self.except.push(mode);
+        self
+    }
+
+    // Whether the two are ever written in the same mode
+    fn overlaps(&self, other: &Mapping) -> bool {
+        match (self.mode, other.mode) {
+            (Some(a), Some(b)) => a == b,
+            (Some(mode), None) => !other.except.contains(&mode),
+            (None, Some(mode)) => !self.except.contains(&mode),
+            (None, None) => true,
+        }
+    }
+}
+
+// Every mapping the device can't satisfy, with what it was for
+pub fn check(
+    device: UINT,
+    mappings: &[Mapping],
+    capabilities: &Capabilities,
+) -> Vec<(Error, String)> {
+    mappings
+        .iter()
+        .filter_map(|mapping| {
+            let error = match mapping.output {
+                Output::Axis(axis) if !capabilities.has_axis(axis) => {
+                    Error::AxisMissing(device, axis)
+                }
+                Output::Button(button) if !capabilities.has_button(button) => {
+                    Error::ButtonOutOfRange(device, button)
+                }
+                Output::Pov(pov) if !capabilities.has_pov(pov) => Error::PovOutOfRange(device, pov),
+                _ => return None,
+            };
+            Some((error, mapping.purpose.clone()))
+        })
+        .collect()
+}
+
+// Every pair of mappings that would write the same output in the same mode,
+// with what each was for
+pub fn clashes(mappings: &[Mapping]) -> Vec<(Output, String, String)> {
+    let mut clashes = Vec::new();
+    for (i, a) in mappings.iter().enumerate() {
+        for b in mappings[i + 1..].iter() {
+            if a.output == b.output && a.overlaps(b) {
+                clashes.push((a.output, a.purpose.clone(), b.purpose.clone()));
+            }
+        }
+    }
+    clashes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capabilities() -> Capabilities {
+        Capabilities {
+            axes: vec![(Axis::X, 0, 0x8000), (Axis::Y, 0, 0x8000)],
+            buttons: 8,
+            discrete_povs: 1,
+            continuous_povs: 0,
+        }
+    }
+
+    #[test]
+    fn satisfied_mappings_pass() {
+        let mappings = [
+            Mapping::axis(Axis::X, "roll"),
+            Mapping::button(8, "right trigger"),
+            Mapping::pov(1, "left thumbstick"),
+        ];
+        assert!(check(1, &mappings, &capabilities()).is_empty());
+    }
+
+    #[test]
+    fn reports_what_is_missing() {
+        let mappings = [
+            Mapping::axis(Axis::X, "roll"),
+            Mapping::axis(Axis::SL0, "left trigger"),
+            Mapping::button(9, "right trigger"),
+            Mapping::button(0, "flick"),
+            Mapping::pov(2, "right thumbstick"),
+        ];
+        let problems = check(1, &mappings, &capabilities());
+        assert_eq!(
+            problems,
+            vec![
+                (Error::AxisMissing(1, Axis::SL0), "left trigger".to_owned()),
+                (Error::ButtonOutOfRange(1, 9), "right trigger".to_owned()),
+                (Error::ButtonOutOfRange(1, 0), "flick".to_owned()),
+                (Error::PovOutOfRange(1, 2), "right thumbstick".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn outputs_taken_over_in_a_mode_do_not_clash() {
+        let mappings = [
+            Mapping::axis(Axis::X, "X").except_in(Mode::Wheel),
+            Mapping::axis(Axis::X, "wheel steering").only_in(Mode::Wheel),
+            Mapping::axis(Axis::Y, "Y").except_in(Mode::Wheel),
+            Mapping::axis(Axis::Y, "pointer Y").only_in(Mode::Pointer),
+        ];
+        assert_eq!(
+            clashes(&mappings),
+            vec![(
+                Output::Axis(Axis::Y),
+                "Y".to_owned(),
+                "pointer Y".to_owned()
+            )]
+        );
+    }
+
+    #[test]
+    fn reports_outputs_used_twice_in_a_mode() {
+        let mappings = [
+            Mapping::axis(Axis::X, "roll"),
+            Mapping::axis(Axis::SL0, "left trigger"),
+            Mapping::axis(Axis::WHL, "left hand latched throttle").only_in(Mode::StickThrottle),
+            Mapping::axis(Axis::WHL, "wheel steering").only_in(Mode::Wheel),
+            Mapping::axis(Axis::SL0, "wheel brake").only_in(Mode::Wheel),
+            Mapping::button(3, "X"),
+            Mapping::button(3, "flick"),
+        ];
+        assert_eq!(
+            clashes(&mappings),
+            vec![
+                (
+                    Output::Axis(Axis::SL0),
+                    "left trigger".to_owned(),
+                    "wheel brake".to_owned()
+                ),
+                (Output::Button(3), "X".to_owned(), "flick".to_owned()),
+            ]
+        );
+    }
+}
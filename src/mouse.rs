@@ -0,0 +1,34 @@
+pub type INT = ::std::os::raw::c_int;
+pub type DWORD = ::std::os::raw::c_ulong;
+
+#[link(name = "user32")]
+extern "system" {
+    pub fn SetCursorPos(X: INT, Y: INT) -> INT;
+    pub fn GetSystemMetrics(nIndex: INT) -> INT;
+    pub fn mouse_event(dwFlags: DWORD, dx: DWORD, dy: DWORD, dwData: DWORD, dwExtraInfo: usize);
+}
+
+const SM_CXSCREEN: INT = 0;
+const SM_CYSCREEN: INT = 1;
+const MOUSEEVENTF_LEFTDOWN: DWORD = 0x0002;
+const MOUSEEVENTF_LEFTUP: DWORD = 0x0004;
+
+// Moves the cursor on the primary screen, from -1 to 1 across and down
+pub fn move_to(x: f32, y: f32) {
+    unsafe {
+        let width = GetSystemMetrics(SM_CXSCREEN) as f32;
+        let height = GetSystemMetrics(SM_CYSCREEN) as f32;
+        SetCursorPos(
+            ((x + 1.0) / 2.0 * width) as INT,
+            ((y + 1.0) / 2.0 * height) as INT,
+        );
+    }
+}
+
+pub fn set_left_button(down: bool) {
+    let flags = match down {
+        true => MOUSEEVENTF_LEFTDOWN,
+        false => MOUSEEVENTF_LEFTUP,
+    };
+    unsafe { mouse_event(flags, 0, 0, 0, 0) }
+}
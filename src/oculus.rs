@@ -0,0 +1,47 @@
+use ovr_sys::*;
+use std::ffi::CStr;
+
+use crate::error::Error;
+
+fn ovr_try<F>(f: F) -> Result<(), Error>
+where
+    F: FnOnce() -> ovrResult,
+{
+    let result = f();
+    if OVR_SUCCESS(result) {
+        Ok(())
+    } else {
+        let mut info: ovrErrorInfo = unsafe { ::std::mem::zeroed() };
+        unsafe { ovr_GetLastErrorInfo(&mut info as *mut _) }
+        let message = unsafe { CStr::from_ptr(info.ErrorString.as_ptr()) };
+        Err(Error::Ovr {
+            code: result,
+            message: message.to_string_lossy().into_owned(),
+        })
+    }
+}
+
+// Starts the Oculus runtime and creates a session, without a VR view of its own
+pub fn connect() -> Result<ovrSession, Error> {
+    unsafe {
+        let mut params: ovrInitParams = ::std::mem::zeroed();
+        // 0x10 is ovrInit_Invisible
+        params.Flags |= ovrInit_RequestVersion + 0x00000010;
+        params.RequestedMinorVersion = OVR_MINOR_VERSION;
+        ovr_try(|| ovr_Initialize(&params as *const _))?;
+        let mut session: ovrSession = ::std::mem::zeroed();
+        let mut luid: ovrGraphicsLuid = ::std::mem::zeroed();
+        if let Err(e) = ovr_try(|| ovr_Create(&mut session as *mut _, &mut luid as *mut _)) {
+            ovr_Shutdown();
+            return Err(e);
+        }
+        Ok(session)
+    }
+}
+
+pub fn disconnect(session: ovrSession) {
+    unsafe {
+        ovr_Destroy(session);
+        ovr_Shutdown();
+    }
+}
@@ -0,0 +1,134 @@
+use ggez::nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+use crate::minmax;
+use crate::transform::Transform;
+use crate::vjoy::Axis;
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Projection {
+    // The ray's yaw and pitch away from straight ahead
+    Angle,
+    // Where the ray hits a flat screen in front of you
+    Plane,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct PointerConfig {
+    // Point with the left controller instead of the right
+    pub left_hand: bool,
+    pub projection: Projection,
+    // Angle only: radians from straight ahead to the edge of the range
+    pub angle: f32,
+    // Plane only: centre of the plane in tracking space, and its width and height, in cm.
+    // The plane faces back towards you.
+    pub plane_centre: [f32; 3],
+    pub plane_size: [f32; 2],
+    // Taken over from the flight axes in pointer mode
+    pub x_axis: Axis,
+    pub y_axis: Axis,
+    // Move the Windows mouse cursor instead, with the trigger as the left mouse button
+    pub mouse: bool,
+}
+
+impl Default for PointerConfig {
+    fn default() -> PointerConfig {
+        PointerConfig {
+            left_hand: false,
+            projection: Projection::Angle,
+            angle: PI / 6.0,
+            plane_centre: [0.0, 100.0, -100.0],
+            plane_size: [100.0, 60.0],
+            x_axis: Axis::X,
+            y_axis: Axis::Y,
+            mouse: false,
+        }
+    }
+}
+
+impl PointerConfig {
+    pub fn hand(&self) -> usize {
+        match self.left_hand {
+            true => 0,
+            false => 1,
+        }
+    }
+
+    // Where the controller is pointing, from -1 to 1 across and down. None
+    // if it's pointing away from the plane.
+    pub fn aim(&self, hand: &Transform) -> Option<(f32, f32)> {
+        let forward = hand.rot * Vector3::new(0.0, 0.0, -1.0);
+        let (x, y) = match self.projection {
+            Projection::Angle => {
+                let yaw = (forward.x).atan2(-forward.z);
+                let pitch = minmax(forward.y, -1.0, 1.0).asin();
+                (yaw / self.angle, -pitch / self.angle)
+            }
+            Projection::Plane => {
+                let centre = self.plane_centre;
+                let distance = centre[2] - hand.pos.z;
+                if forward.z == 0.0 || distance / forward.z <= 0.0 {
+                    return None;
+                }
+                let hit = hand.pos + forward * (distance / forward.z);
+                (
+                    (hit.x - centre[0]) / (self.plane_size[0] / 2.0),
+                    -(hit.y - centre[1]) / (self.plane_size[1] / 2.0),
+                )
+            }
+        };
+        Some((minmax(x, -1.0, 1.0), minmax(y, -1.0, 1.0)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transform::rounded;
+    use ggez::nalgebra::{Point3, UnitQuaternion};
+
+    fn hand(pos: [f32; 3], yaw: f32, pitch: f32) -> Transform {
+        Transform {
+            pos: Point3::new(pos[0], pos[1], pos[2]),
+            rot: UnitQuaternion::from_euler_angles(pitch, yaw, 0.0),
+        }
+    }
+
+    fn aim(config: &PointerConfig, hand: &Transform) -> Option<(f32, f32)> {
+        config.aim(hand).map(|(x, y)| (rounded(x), rounded(y)))
+    }
+
+    #[test]
+    fn angle_is_measured_from_straight_ahead() {
+        let config = PointerConfig::default();
+        assert_eq!(aim(&config, &hand([0.0; 3], 0.0, 0.0)), Some((0.0, 0.0)));
+        // Half of the default 30 degrees to the right, then up
+        assert_eq!(
+            aim(&config, &hand([0.0; 3], -PI / 12.0, 0.0)),
+            Some((0.5, 0.0))
+        );
+        assert_eq!(
+            aim(&config, &hand([0.0; 3], 0.0, PI / 12.0)),
+            Some((0.0, -0.5))
+        );
+        assert_eq!(
+            aim(&config, &hand([0.0; 3], -PI / 2.0, 0.0)),
+            Some((1.0, 0.0))
+        );
+    }
+
+    #[test]
+    fn plane_is_where_the_ray_hits_it() {
+        let mut config = PointerConfig::default();
+        config.projection = Projection::Plane;
+        let centre = hand([0.0, 100.0, 0.0], 0.0, 0.0);
+        assert_eq!(aim(&config, &centre), Some((0.0, 0.0)));
+        let corner = hand([25.0, 115.0, 0.0], 0.0, 0.0);
+        assert_eq!(aim(&config, &corner), Some((0.5, -0.5)));
+        let behind = hand([0.0, 100.0, 0.0], PI, 0.0);
+        assert_eq!(aim(&config, &behind), None);
+    }
+}
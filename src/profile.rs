@@ -0,0 +1,217 @@
+use ovr_sys::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::axes::AxesConfig;
+use crate::cockpit::Control;
+use crate::gesture::GestureConfig;
+use crate::hand::{HandConfig, Style};
+use crate::pointer::PointerConfig;
+use crate::tracking::TrackingConfig;
+use crate::vjoy::Axis;
+use crate::wheel::WheelConfig;
+use crate::yoke::YokeConfig;
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    // Left hand throttle, right hand stick
+    StickThrottle,
+    // Both hands on a yoke
+    Yoke,
+    // Both hands on a steering wheel, triggers for throttle and brake
+    Wheel,
+    // Point a controller to move two axes, or the mouse
+    Pointer,
+}
+
+impl Default for Mode {
+    fn default() -> Mode {
+        Mode::StickThrottle
+    }
+}
+
+// A button on the Touch controllers
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TouchButton {
+    A,
+    B,
+    X,
+    Y,
+    LeftThumb,
+    RightThumb,
+    Menu,
+    None,
+}
+
+impl TouchButton {
+    // Its bit in ovrInputState.Buttons
+    pub fn mask(self) -> i32 {
+        match self {
+            TouchButton::A => ovrButton_A,
+            TouchButton::B => ovrButton_B,
+            TouchButton::X => ovrButton_X,
+            TouchButton::Y => ovrButton_Y,
+            TouchButton::LeftThumb => ovrButton_LThumb,
+            TouchButton::RightThumb => ovrButton_RThumb,
+            TouchButton::Menu => ovrButton_Enter,
+            TouchButton::None => 0,
+        }
+    }
+
+    // The vJoy button `--input` passes it through to, 0 for none
+    pub fn input_button(self) -> u8 {
+        match self {
+            TouchButton::None => 0,
+            button => button as u8 + 1,
+        }
+    }
+}
+
+// Plain values have to come before tables in every struct here, otherwise
+// the toml serializer refuses to write the profile back out.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub mode: Mode,
+    // Modes that clicking both thumbsticks cycles through
+    pub modes: Vec<Mode>,
+    // Centre the axes and stop following the controllers while the headset is off
+    pub pause_when_unmounted: bool,
+    // The same while Oculus Home, the Dash or another app has input focus
+    pub pause_when_unfocused: bool,
+    // Seconds without output before the joystick is centred, 0 to never
+    pub watchdog_timeout: f32,
+    // Times a second the controllers are read and vJoy is written
+    pub update_rate: f32,
+    // Trims the held axes, or clears the trim when nothing is held
+    pub trim_button: TouchButton,
+    pub left: HandConfig,
+    pub right: HandConfig,
+    pub yoke: YokeConfig,
+    pub wheel: WheelConfig,
+    pub pointer: PointerConfig,
+    pub axes: AxesConfig,
+    pub gestures: GestureConfig,
+    pub tracking: TrackingConfig,
+    pub controls: Vec<Control>,
+}
+
+impl Default for Profile {
+    fn default() -> Profile {
+        Profile {
+            mode: Mode::default(),
+            modes: vec![Mode::StickThrottle, Mode::Yoke, Mode::Wheel, Mode::Pointer],
+            pause_when_unmounted: false,
+            pause_when_unfocused: false,
+            watchdog_timeout: 0.5,
+            update_rate: 250.0,
+            trim_button: TouchButton::None,
+            left: HandConfig::default(),
+            right: HandConfig::default(),
+            yoke: YokeConfig::default(),
+            wheel: WheelConfig::default(),
+            pointer: PointerConfig::default(),
+            axes: AxesConfig::default(),
+            gestures: GestureConfig::default(),
+            tracking: TrackingConfig::default(),
+            controls: Vec::new(),
+        }
+    }
+}
+
+impl Profile {
+    pub fn load(path: &Path) -> Result<Profile, String> {
+        if !path.exists() {
+            return Ok(Profile::default());
+        }
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read profile {}: {}", path.display(), e))?;
+        toml::from_str(&text)
+            .map_err(|e| format!("Could not parse profile {}: {}", path.display(), e))
+    }
+
+    // Axes the mode writes itself, taking them over from the flight axes
+    pub fn mode_axes(&self, mode: Mode) -> Vec<Axis> {
+        match mode {
+            Mode::StickThrottle => [&self.left, &self.right]
+                .iter()
+                .filter(|config| config.style == Style::LatchedThrottle)
+                .map(|config| config.latched_axis)
+                .collect(),
+            Mode::Wheel => vec![
+                self.wheel.steering_axis,
+                self.wheel.throttle_axis,
+                self.wheel.brake_axis,
+            ],
+            Mode::Pointer if !self.pointer.mouse => {
+                vec![self.pointer.x_axis, self.pointer.y_axis]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    // Axes whose resting place in the mode is at one end rather than in the
+    // middle: the triggers, throttles, brakes and cockpit levers. An axis the
+    // mode takes over rests wherever the mode puts it, even if it's also a
+    // trigger or lever.
+    pub fn resting_axes(&self, mode: Mode, trigger_axes: bool) -> Vec<Axis> {
+        let mut axes = Vec::new();
+        if trigger_axes {
+            axes.push(Axis::SL0);
+            axes.push(Axis::SL1);
+        }
+        axes.extend(self.controls.iter().filter_map(|control| control.axis));
+        let taken = self.mode_axes(mode);
+        axes.retain(|axis| !taken.contains(axis));
+        let own = match mode {
+            // The latched throttles
+            Mode::StickThrottle => self.mode_axes(mode),
+            Mode::Wheel => vec![self.wheel.throttle_axis, self.wheel.brake_axis],
+            Mode::Yoke | Mode::Pointer => Vec::new(),
+        };
+        for axis in own {
+            if !axes.contains(&axis) {
+                axes.push(axis);
+            }
+        }
+        axes
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let text = toml::to_string_pretty(self)
+            .map_err(|e| format!("Could not serialize profile: {}", e))?;
+        fs::write(path, text)
+            .map_err(|e| format!("Could not write profile {}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile() -> Profile {
+        let mut profile = Profile::default();
+        profile.left.style = Style::LatchedThrottle;
+        profile.left.latched_axis = Axis::WHL;
+        profile.wheel.steering_axis = Axis::WHL;
+        profile.pointer.x_axis = Axis::SL0;
+        profile
+    }
+
+    #[test]
+    fn resting_axes_follow_the_mode() {
+        let profile = profile();
+        let stick = profile.resting_axes(Mode::StickThrottle, true);
+        assert_eq!(stick, vec![Axis::SL0, Axis::SL1, Axis::WHL]);
+        // The steering shares the latched throttle's axis, but stays centred
+        let wheel = profile.resting_axes(Mode::Wheel, true);
+        assert_eq!(wheel, vec![Axis::SL1, Axis::SL0]);
+        // The pointer shares the left trigger's axis, and rests in the middle
+        let pointer = profile.resting_axes(Mode::Pointer, true);
+        assert_eq!(pointer, vec![Axis::SL1]);
+        assert!(profile.resting_axes(Mode::Yoke, false).is_empty());
+    }
+}
@@ -0,0 +1,61 @@
+use std::time::{Duration, Instant};
+
+const FIRST_DELAY: Duration = Duration::from_secs(1);
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+// Paces attempts at something that keeps failing, waiting twice as long
+// after each failure
+pub struct Retry {
+    delay: Duration,
+    next: Option<Instant>,
+}
+
+impl Retry {
+    pub fn new() -> Retry {
+        Retry {
+            delay: FIRST_DELAY,
+            next: None,
+        }
+    }
+
+    pub fn due(&self) -> bool {
+        self.next.map_or(true, |next| Instant::now() >= next)
+    }
+
+    // Returns how long until the next attempt
+    pub fn failed(&mut self) -> Duration {
+        let delay = self.delay;
+        self.next = Some(Instant::now() + delay);
+        self.delay = (delay * 2).min(MAX_DELAY);
+        delay
+    }
+
+    pub fn succeeded(&mut self) {
+        self.delay = FIRST_DELAY;
+        self.next = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backs_off_up_to_a_limit() {
+        let mut retry = Retry::new();
+        assert!(retry.due());
+        let delays: Vec<u64> = (0..7).map(|_| retry.failed().as_secs()).collect();
+        assert_eq!(delays, vec![1, 2, 4, 8, 16, 30, 30]);
+        assert!(!retry.due());
+    }
+
+    #[test]
+    fn success_starts_again() {
+        let mut retry = Retry::new();
+        retry.failed();
+        retry.failed();
+        retry.succeeded();
+        assert!(retry.due());
+        assert_eq!(retry.failed(), FIRST_DELAY);
+    }
+}
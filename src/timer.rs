@@ -0,0 +1,83 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub type UINT = ::std::os::raw::c_uint;
+
+#[link(name = "winmm")]
+extern "system" {
+    pub fn timeBeginPeriod(uPeriod: UINT) -> UINT;
+    pub fn timeEndPeriod(uPeriod: UINT) -> UINT;
+}
+
+// Ticks at a fixed rate
+pub struct Timer {
+    period: Duration,
+    next: Instant,
+}
+
+impl Timer {
+    pub fn new(rate: f32) -> Timer {
+        // Windows sleeps in 15ms steps otherwise
+        unsafe {
+            timeBeginPeriod(1);
+        }
+        Timer {
+            period: Duration::from_secs_f32(1.0 / rate.max(1.0)),
+            next: Instant::now(),
+        }
+    }
+
+    // Waits for the next tick. With the 1ms timer resolution asked for in
+    // `new`, sleeping overshoots by about a millisecond at most.
+    pub fn wait(&mut self) {
+        let now = Instant::now();
+        self.next = next_tick(self.next, self.period, now);
+        if self.next > now {
+            thread::sleep(self.next - now);
+        }
+    }
+}
+
+// When the tick after `last` is due. If it's already late, it doesn't try to
+// catch up: the ticks carry on a period apart from now.
+fn next_tick(last: Instant, period: Duration, now: Instant) -> Instant {
+    let next = last + period;
+    match next <= now {
+        true => now,
+        false => next,
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        unsafe {
+            timeEndPeriod(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PERIOD: Duration = Duration::from_millis(10);
+
+    #[test]
+    fn ticks_a_period_apart_without_drifting() {
+        let start = Instant::now();
+        // Woken up a little late, the next tick is still on the original beat
+        let first = next_tick(start, PERIOD, start + Duration::from_millis(1));
+        assert_eq!(first, start + PERIOD);
+        let second = next_tick(first, PERIOD, first + Duration::from_millis(1));
+        assert_eq!(second, start + PERIOD * 2);
+    }
+
+    #[test]
+    fn late_ticks_do_not_catch_up() {
+        let start = Instant::now();
+        let late = start + PERIOD * 5;
+        assert_eq!(next_tick(start, PERIOD, late), late);
+        // ...and carry on a period apart from there
+        assert_eq!(next_tick(late, PERIOD, late), late + PERIOD);
+    }
+}
@@ -0,0 +1,158 @@
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LossPolicy {
+    // Keep the axes where the hand was last tracked
+    Hold,
+    // Drop the axes straight back to centre
+    Centre,
+    // Ease the axes back to centre over fade_time
+    Fade,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct TrackingConfig {
+    // What happens to the axes a hand drives while it isn't tracked. The hand
+    // itself stays where it was last tracked either way.
+    pub on_loss: LossPolicy,
+    // Fade only: seconds
+    pub fade_time: f32,
+    // Seconds to buzz a controller when its tracking comes back, 0 for none
+    pub recovery_buzz: f32,
+}
+
+impl Default for TrackingConfig {
+    fn default() -> TrackingConfig {
+        TrackingConfig {
+            on_loss: LossPolicy::Centre,
+            fade_time: 1.0,
+            recovery_buzz: 0.2,
+        }
+    }
+}
+
+pub struct Tracking {
+    // Seconds each hand has been lost for
+    lost: [Option<f32>; 2],
+    // Seconds of recovery buzz left
+    buzz: [f32; 2],
+    haptics: [Option<f32>; 2],
+    last: Option<Instant>,
+}
+
+impl Tracking {
+    pub fn new() -> Tracking {
+        Tracking {
+            lost: [None; 2],
+            buzz: [0.0; 2],
+            haptics: [None; 2],
+            last: None,
+        }
+    }
+
+    pub fn tracked(&self, hand: usize) -> bool {
+        self.lost[hand].is_none()
+    }
+
+    // Vibration amplitude to set on a hand this tick, if it changed
+    pub fn haptics(&self, hand: usize) -> Option<f32> {
+        self.haptics[hand]
+    }
+
+    // How much of a hand's deflection to let through, from 0 to 1
+    pub fn gain(&self, config: &TrackingConfig, hand: usize) -> f32 {
+        match (self.lost[hand], config.on_loss) {
+            (None, _) | (Some(_), LossPolicy::Hold) => 1.0,
+            (Some(_), LossPolicy::Centre) => 0.0,
+            (Some(lost), LossPolicy::Fade) if config.fade_time > 0.0 => {
+                (1.0 - lost / config.fade_time).max(0.0)
+            }
+            (Some(_), LossPolicy::Fade) => 0.0,
+        }
+    }
+
+    pub fn update(&mut self, config: &TrackingConfig, tracked: [bool; 2]) {
+        let now = Instant::now();
+        let elapsed = match self.last {
+            Some(last) => (now - last).as_secs_f32(),
+            None => 0.0,
+        };
+        self.last = Some(now);
+        self.step(config, tracked, elapsed);
+    }
+
+    fn step(&mut self, config: &TrackingConfig, tracked: [bool; 2], elapsed: f32) {
+        for hand in 0..2 {
+            self.haptics[hand] = None;
+            if !tracked[hand] {
+                if self.lost[hand].is_none() {
+                    log!("Lost tracking of the {} hand", ["left", "right"][hand]);
+                }
+                self.lost[hand] = Some(self.lost[hand].map_or(0.0, |lost| lost + elapsed));
+            } else if self.lost[hand].take().is_some() {
+                log!("Tracking of the {} hand is back", ["left", "right"][hand]);
+                if config.recovery_buzz > 0.0 {
+                    self.buzz[hand] = config.recovery_buzz;
+                    self.haptics[hand] = Some(1.0);
+                }
+            } else if self.buzz[hand] > 0.0 {
+                self.buzz[hand] -= elapsed;
+                if self.buzz[hand] <= 0.0 {
+                    self.haptics[hand] = Some(0.0);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(on_loss: LossPolicy) -> TrackingConfig {
+        TrackingConfig {
+            on_loss,
+            ..TrackingConfig::default()
+        }
+    }
+
+    #[test]
+    fn policies_on_loss() {
+        let mut tracking = Tracking::new();
+        tracking.step(&config(LossPolicy::Hold), [true, false], 0.0);
+        assert_eq!(tracking.gain(&config(LossPolicy::Hold), 1), 1.0);
+        assert_eq!(tracking.gain(&config(LossPolicy::Centre), 1), 0.0);
+        assert_eq!(tracking.gain(&config(LossPolicy::Centre), 0), 1.0);
+    }
+
+    #[test]
+    fn fade_eases_out() {
+        let config = config(LossPolicy::Fade);
+        let mut tracking = Tracking::new();
+        tracking.step(&config, [false, true], 0.0);
+        assert_eq!(tracking.gain(&config, 0), 1.0);
+        tracking.step(&config, [false, true], 0.5);
+        assert_eq!(tracking.gain(&config, 0), 0.5);
+        tracking.step(&config, [false, true], 1.0);
+        assert_eq!(tracking.gain(&config, 0), 0.0);
+    }
+
+    #[test]
+    fn buzzes_on_recovery() {
+        let config = config(LossPolicy::Centre);
+        let mut tracking = Tracking::new();
+        tracking.step(&config, [true, false], 0.0);
+        assert_eq!(tracking.haptics(1), None);
+        tracking.step(&config, [true, true], 0.1);
+        assert!(tracking.tracked(1));
+        assert_eq!(tracking.haptics(1), Some(1.0));
+        tracking.step(&config, [true, true], 0.1);
+        assert_eq!(tracking.haptics(1), None);
+        tracking.step(&config, [true, true], 0.2);
+        assert_eq!(tracking.haptics(1), Some(0.0));
+        assert_eq!(tracking.haptics(0), None);
+    }
+}
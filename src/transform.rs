@@ -0,0 +1,51 @@
+use ggez::nalgebra::{self as na, Point3, Vector3};
+use ovr_sys::ovrPosef;
+
+#[derive(Debug, Clone)]
+pub struct Transform {
+    pub pos: Point3<f32>,
+    pub rot: na::UnitQuaternion<f32>,
+}
+
+impl Transform {
+    pub fn new(pose: ovrPosef) -> Transform {
+        let rot = na::UnitQuaternion::new_normalize(na::Quaternion::new(
+            pose.Orientation.w,
+            pose.Orientation.x,
+            pose.Orientation.y,
+            pose.Orientation.z,
+        ));
+        let pos: Point3<f32> = Point3::from_slice(&[
+            pose.Position.x * 100.0,
+            pose.Position.y * 100.0,
+            pose.Position.z * 100.0,
+        ]);
+        Transform { pos, rot }
+    }
+    pub fn default() -> Transform {
+        Transform {
+            pos: Point3::origin(),
+            rot: na::UnitQuaternion::identity(),
+        }
+    }
+}
+
+// Angle of a line above the horizontal
+pub fn elevation(line: &Vector3<f32>) -> f32 {
+    line.y.atan2((line.x * line.x + line.z * line.z).sqrt())
+}
+
+// A hand at a point in tracking space, in cm, facing straight ahead
+#[cfg(test)]
+pub fn at(x: f32, y: f32, z: f32) -> Transform {
+    Transform {
+        pos: Point3::new(x, y, z),
+        rot: na::UnitQuaternion::identity(),
+    }
+}
+
+// To the nearest thousandth, so tests don't need the trigonometry to come out exact
+#[cfg(test)]
+pub fn rounded(value: f32) -> f32 {
+    (value * 1000.0).round() / 1000.0
+}
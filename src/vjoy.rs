@@ -1,3 +1,7 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
 pub type BOOL = ::std::os::raw::c_int;
 pub type BYTE = ::std::os::raw::c_uchar;
 pub type CHAR = ::std::os::raw::c_char;
@@ -16,18 +20,115 @@ pub type VOID = ::std::os::raw::c_void;
 
 extern "C" {
     pub fn AcquireVJD(rID: UINT) -> BOOL;
+    pub fn RelinquishVJD(rID: UINT);
+    pub fn GetVJDStatus(rID: UINT) -> INT;
     pub fn FfbStart(rID: UINT) -> BOOL;
     pub fn vJoyEnabled() -> BOOL;
     pub fn ResetAll() -> BOOL;
     pub fn SetAxis(Value: LONG, rID: UINT, Axis: UINT) -> BOOL;
     pub fn SetDiscPov(Value: INT, rID: UINT, nPov: UCHAR) -> BOOL;
     pub fn SetBtn(Value: BOOL, rID: UINT, nBtn: UCHAR) -> BOOL; // Write Value to a given button defined in the specified VDJ
+    pub fn GetVJDAxisExist(rID: UINT, Axis: UINT) -> BOOL;
+    pub fn GetVJDAxisMin(rID: UINT, Axis: UINT, Min: *mut LONG) -> BOOL;
+    pub fn GetVJDAxisMax(rID: UINT, Axis: UINT, Max: *mut LONG) -> BOOL;
+    pub fn GetVJDButtonNumber(rID: UINT) -> INT;
+    pub fn GetVJDDiscPovNumber(rID: UINT) -> INT;
+    pub fn GetVJDContPovNumber(rID: UINT) -> INT;
+    pub fn UpdateVJD(rID: UINT, pData: PVOID) -> BOOL;
 }
 
 pub struct Joystick {
     device: UINT,
+    capabilities: Capabilities,
+    // Built up over a tick, then sent all at once by flush
+    report: JOYSTICK_POSITION_V2,
+    // What the device was last sent, if it's still showing it
+    sent: Option<JOYSTICK_POSITION_V2>,
+    // Axes that rest at their minimum rather than in the middle, like triggers
+    // and throttles
+    resting: Vec<Axis>,
+}
+
+// VjdStat
+const VJD_STAT_OWN: INT = 0;
+const VJD_STAT_FREE: INT = 1;
+const VJD_STAT_BUSY: INT = 2;
+const VJD_STAT_MISS: INT = 3;
+// What vJoy axes go between, for axes the device didn't report
+const AXIS_MIN: i32 = 1;
+const AXIS_MAX: i32 = 0x8000;
+const MAX_BUTTONS: UCHAR = 128;
+const MAX_POVS: UCHAR = 4;
+const AXES: [Axis; 9] = [
+    Axis::X,
+    Axis::Y,
+    Axis::Z,
+    Axis::RX,
+    Axis::RY,
+    Axis::RZ,
+    Axis::SL0,
+    Axis::SL1,
+    Axis::WHL,
+];
+
+// What a device has been set up with in Configure vJoy
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Capabilities {
+    // Each axis the device has, with its minimum and maximum
+    pub axes: Vec<(Axis, i32, i32)>,
+    pub buttons: u8,
+    pub discrete_povs: u8,
+    pub continuous_povs: u8,
+}
+
+impl Capabilities {
+    // Works whether or not the device is acquired
+    pub fn query(device: UINT) -> Capabilities {
+        let mut axes = Vec::new();
+        for &axis in AXES.iter() {
+            if unsafe { GetVJDAxisExist(device, axis as UINT) } == 0 {
+                continue;
+            }
+            let mut min: LONG = 0;
+            let mut max: LONG = 0;
+            unsafe {
+                GetVJDAxisMin(device, axis as UINT, &mut min as *mut LONG);
+                GetVJDAxisMax(device, axis as UINT, &mut max as *mut LONG);
+            }
+            axes.push((axis, min as i32, max as i32));
+        }
+        let count = |n: INT| n.max(0).min(255) as u8;
+        unsafe {
+            Capabilities {
+                axes,
+                buttons: count(GetVJDButtonNumber(device)),
+                discrete_povs: count(GetVJDDiscPovNumber(device)),
+                continuous_povs: count(GetVJDContPovNumber(device)),
+            }
+        }
+    }
+
+    pub fn range(&self, axis: Axis) -> (i32, i32) {
+        self.axes
+            .iter()
+            .find(|&&(a, _, _)| a == axis)
+            .map_or((AXIS_MIN, AXIS_MAX), |&(_, min, max)| (min, max))
+    }
+
+    pub fn has_axis(&self, axis: Axis) -> bool {
+        self.axes.iter().any(|&(a, _, _)| a == axis)
+    }
+
+    pub fn has_button(&self, button: u8) -> bool {
+        button >= 1 && button <= self.buttons
+    }
+
+    pub fn has_pov(&self, pov: u8) -> bool {
+        pov >= 1 && pov <= self.discrete_povs
+    }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Axis {
     X = 0x30,
     Y = 0x31,
@@ -50,76 +151,459 @@ pub enum PovDirection {
     NEUTRAL = -1
 }
 
+// Everything on a device at once, as UpdateVJD takes it
+#[allow(non_camel_case_types, non_snake_case)]
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+struct JOYSTICK_POSITION_V2 {
+    bDevice: BYTE,
+    wThrottle: LONG,
+    wRudder: LONG,
+    wAileron: LONG,
+    wAxisX: LONG,
+    wAxisY: LONG,
+    wAxisZ: LONG,
+    wAxisXRot: LONG,
+    wAxisYRot: LONG,
+    wAxisZRot: LONG,
+    wSlider: LONG,
+    wDial: LONG,
+    wWheel: LONG,
+    wAxisVX: LONG,
+    wAxisVY: LONG,
+    wAxisVZ: LONG,
+    wAxisVBRX: LONG,
+    wAxisVBRY: LONG,
+    wAxisVBRZ: LONG,
+    // Buttons 1-32, one bit each
+    lButtons: LONG,
+    // Discrete hats 1-4, four bits each with 0xF for centred
+    bHats: DWORD,
+    bHatsEx1: DWORD,
+    bHatsEx2: DWORD,
+    bHatsEx3: DWORD,
+    // Buttons 33-64, 65-96 and 97-128
+    lButtonsEx1: LONG,
+    lButtonsEx2: LONG,
+    lButtonsEx3: LONG,
+}
+
+impl JOYSTICK_POSITION_V2 {
+    // Axes centred or at their minimum if they're resting, buttons up and hats centred
+    fn neutral(
+        device: UINT,
+        capabilities: &Capabilities,
+        resting: &[Axis],
+    ) -> JOYSTICK_POSITION_V2 {
+        let mut report = JOYSTICK_POSITION_V2 {
+            bDevice: device as BYTE,
+            bHats: !0,
+            bHatsEx1: !0,
+            bHatsEx2: !0,
+            bHatsEx3: !0,
+            ..Default::default()
+        };
+        for &axis in AXES.iter() {
+            report.set_axis(axis, rest(axis, capabilities, resting));
+        }
+        report
+    }
+
+    fn set_axis(&mut self, axis: Axis, value: i32) {
+        let field = match axis {
+            Axis::X => &mut self.wAxisX,
+            Axis::Y => &mut self.wAxisY,
+            Axis::Z => &mut self.wAxisZ,
+            Axis::RX => &mut self.wAxisXRot,
+            Axis::RY => &mut self.wAxisYRot,
+            Axis::RZ => &mut self.wAxisZRot,
+            Axis::SL0 => &mut self.wSlider,
+            Axis::SL1 => &mut self.wDial,
+            Axis::WHL => &mut self.wWheel,
+            Axis::POV => return,
+        };
+        *field = value as LONG;
+    }
+
+    // Buttons from 1 to MAX_BUTTONS
+    fn set_btn(&mut self, button: u8, state: bool) {
+        let index = (button - 1) as usize;
+        let field = match index / 32 {
+            0 => &mut self.lButtons,
+            1 => &mut self.lButtonsEx1,
+            2 => &mut self.lButtonsEx2,
+            _ => &mut self.lButtonsEx3,
+        };
+        let bit = (1u32 << (index % 32)) as LONG;
+        match state {
+            true => *field |= bit,
+            false => *field &= !bit,
+        }
+    }
+
+    // Hats from 1 to MAX_POVS
+    fn set_pov(&mut self, pov: u8, value: PovDirection) {
+        let shift = (pov - 1) as u32 * 4;
+        let nibble = (value as i32 & 0xF) as DWORD;
+        self.bHats = (self.bHats & !(0xF << shift)) | (nibble << shift);
+    }
+}
+
 impl Joystick {
     pub fn new(device: UINT) -> Joystick {
-        Joystick { device: device }
+        Joystick::with_capabilities(device, Capabilities::query(device))
+    }
+
+    // Skips asking vJoy what the device has, for testing the setters without it
+    fn with_capabilities(device: UINT, capabilities: Capabilities) -> Joystick {
+        Joystick {
+            device: device,
+            report: JOYSTICK_POSITION_V2::neutral(device, &capabilities, &[]),
+            capabilities,
+            sent: None,
+            resting: Vec::new(),
+        }
+    }
+
+    // Sets which axes `neutral`, `clear` and `rest_axis` put at their minimum
+    // instead of the middle. The report itself is left alone.
+    pub fn rest_at_minimum(&mut self, axes: Vec<Axis>) {
+        self.resting = axes;
+    }
+
+    pub fn device(&self) -> UINT {
+        self.device
     }
 
     pub fn is_enabled(&self) -> bool {
         unsafe { vJoyEnabled() == 1 }
     }
 
-    pub fn acquire(&mut self) -> Result<(), String> {
+    // Whether this process still holds the device, e.g. vJoy hasn't been
+    // reconfigured or disabled since it was acquired
+    pub fn is_owned(&self) -> bool {
+        unsafe { GetVJDStatus(self.device) == VJD_STAT_OWN }
+    }
+
+    pub fn acquire(&mut self) -> Result<(), Error> {
         if !self.is_enabled() {
-            return Err("vJoy not enabled.".to_owned());
+            return Err(Error::VJoyDisabled);
+        }
+
+        match unsafe { GetVJDStatus(self.device) } {
+            VJD_STAT_OWN | VJD_STAT_FREE => {}
+            VJD_STAT_BUSY => return Err(Error::DeviceOwned(self.device)),
+            VJD_STAT_MISS => return Err(Error::DeviceMissing(self.device)),
+            _ => return Err(Error::DeviceUnavailable(self.device)),
         }
 
         if unsafe { AcquireVJD(self.device) == 0 } {
-            return Err(format!("Could not acquire device {}", self.device));
+            return Err(Error::DeviceUnavailable(self.device));
         }
 
         if unsafe { FfbStart(self.device) == 0 } {
-            return Err(format!("Could not start FFB on device {}", self.device));
+            return Err(Error::FfbUnavailable(self.device));
         }
 
+        // The device may have been reconfigured since
+        self.capabilities = Capabilities::query(self.device);
+        self.clear();
+        self.sent = None;
+
         Ok(())
     }
 
-    pub fn reset(&mut self) -> Result<(), String> {
+    pub fn reset(&mut self) -> Result<(), Error> {
         if unsafe { ResetAll() == 0 } {
-            return Err("Could not reset device.".to_owned());
+            return Err(Error::ResetFailed);
         }
+        self.sent = None;
         Ok(())
     }
 
-    pub fn set_axis(&mut self, axis: Axis, value: i32) -> Result<(), String> {
-        if unsafe { SetAxis(value, self.device, axis as u32) } == 0 {
-            return Err(format!("Could not update device {}", self.device));
+    // As of when the device was acquired
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    // The setters below only change the report, which flush then sends
+
+    // Centred axes go from -1 to 1, over the device's range for the axis
+    pub fn set_axis(&mut self, axis: Axis, value: f32) -> Result<(), Error> {
+        if !self.capabilities.has_axis(axis) {
+            return Err(Error::AxisMissing(self.device, axis));
         }
+        let (min, max) = self.capabilities.range(axis);
+        self.report.set_axis(axis, scale(value, min, max));
         Ok(())
     }
 
-    pub fn set_btn(&mut self, button: u8, state: bool) -> Result<(), String> {
-        if unsafe { SetBtn(state as i32, self.device, button as UCHAR) } == 0 {
-            return Err(format!("Could not update device {}", self.device));
+    // Axes that rest at one end, like triggers, go from 0 to 1
+    pub fn set_axis_unit(&mut self, axis: Axis, value: f32) -> Result<(), Error> {
+        if !self.capabilities.has_axis(axis) {
+            return Err(Error::AxisMissing(self.device, axis));
         }
+        let (min, max) = self.capabilities.range(axis);
+        self.report.set_axis(axis, scale_unit(value, min, max));
         Ok(())
     }
 
-    pub fn set_pov(&mut self, pov: u8, value: PovDirection) -> Result<(), String> {
-        if unsafe { SetDiscPov(value as i32, self.device, pov) } == 0 {
-            return Err(format!("Could not update device {}", self.device));
+    pub fn set_btn(&mut self, button: u8, state: bool) -> Result<(), Error> {
+        if !self.capabilities.has_button(button) || button > MAX_BUTTONS {
+            return Err(Error::ButtonOutOfRange(self.device, button));
+        }
+        self.report.set_btn(button, state);
+        Ok(())
+    }
+
+    pub fn set_pov(&mut self, pov: u8, value: PovDirection) -> Result<(), Error> {
+        if !self.capabilities.has_pov(pov) || pov > MAX_POVS {
+            return Err(Error::PovOutOfRange(self.device, pov));
+        }
+        self.report.set_pov(pov, value);
+        Ok(())
+    }
+
+    // Sends everything set since the last flush in one go, so the game never
+    // sees half a tick. Nothing is sent if nothing changed.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        if self.sent == Some(self.report) {
+            return Ok(());
+        }
+        let report = &mut self.report as *mut JOYSTICK_POSITION_V2 as PVOID;
+        if unsafe { UpdateVJD(self.device, report) } == 0 {
+            self.sent = None;
+            return match self.is_owned() {
+                true => Err(Error::UpdateFailed(self.device)),
+                false => Err(Error::NotAcquired(self.device)),
+            };
         }
+        self.sent = Some(self.report);
         Ok(())
     }
+
+    // Something else wrote to the device, so send the whole report next flush
+    pub fn invalidate(&mut self) {
+        self.sent = None;
+    }
+
+    // Centres every axis, or puts it at its minimum if it's resting, releases
+    // every button and centres every hat in the report, to be sent by the next flush
+    pub fn clear(&mut self) {
+        self.report = JOYSTICK_POSITION_V2::neutral(self.device, &self.capabilities, &self.resting);
+    }
+
+    // Puts one axis back where `clear` leaves it, to be sent by the next flush
+    pub fn rest_axis(&mut self, axis: Axis) {
+        let value = rest(axis, &self.capabilities, &self.resting);
+        self.report.set_axis(axis, value);
+    }
+
+    // Clears the report and sends it straight away
+    pub fn neutral(&mut self) {
+        self.capabilities = Capabilities::query(self.device);
+        self.clear();
+        self.sent = None;
+        let _ = self.flush();
+    }
+
+    pub fn relinquish(&mut self) {
+        unsafe { RelinquishVJD(self.device) }
+    }
+}
+
+// An axis' minimum if it's resting, otherwise its middle
+fn rest(axis: Axis, capabilities: &Capabilities, resting: &[Axis]) -> i32 {
+    let (min, max) = capabilities.range(axis);
+    match resting.contains(&axis) {
+        true => min,
+        false => scale(0.0, min, max),
+    }
+}
+
+// Maps -1..1 onto min..max. 0 lands exactly on the centre vJoy uses, which is
+// rounded down when the range has an even number of steps.
+fn scale(value: f32, min: i32, max: i32) -> i32 {
+    let value = value.max(-1.0).min(1.0);
+    let centre = min + (max - min) / 2;
+    let half = match value < 0.0 {
+        true => centre - min,
+        false => max - centre,
+    };
+    centre + (value * half as f32).round() as i32
+}
+
+// Maps 0..1 onto min..max
+fn scale_unit(value: f32, min: i32, max: i32) -> i32 {
+    let value = value.max(0.0).min(1.0);
+    min + (value * (max - min) as f32).round() as i32
 }
 
 #[cfg(test)]
 mod tests {
+    use super::{scale, scale_unit, Capabilities, JOYSTICK_POSITION_V2};
     use crate::*;
+
+    #[test]
+    fn scales_centred_values() {
+        assert_eq!(scale(-1.0, 1, 0x8000), 1);
+        assert_eq!(scale(0.0, 1, 0x8000), 0x4000);
+        assert_eq!(scale(1.0, 1, 0x8000), 0x8000);
+        assert_eq!(scale(0.5, 0, 100), 75);
+        assert_eq!(scale(-0.5, 0, 100), 25);
+        assert_eq!(scale(0.0, -100, 100), 0);
+    }
+
+    #[test]
+    fn scales_unit_values() {
+        assert_eq!(scale_unit(0.0, 1, 0x8000), 1);
+        assert_eq!(scale_unit(1.0, 1, 0x8000), 0x8000);
+        assert_eq!(scale_unit(0.5, 0, 100), 50);
+        assert_eq!(scale_unit(0.25, -100, 100), -50);
+    }
+
+    #[test]
+    fn clamps_out_of_range_values() {
+        assert_eq!(scale(2.0, 1, 0x8000), 0x8000);
+        assert_eq!(scale(-2.0, 1, 0x8000), 1);
+        assert_eq!(scale_unit(-0.5, 1, 0x8000), 1);
+        assert_eq!(scale_unit(1.5, 1, 0x8000), 0x8000);
+    }
+
+    #[test]
+    fn report_sets_the_right_fields() {
+        let capabilities = Capabilities::default();
+        let mut report = JOYSTICK_POSITION_V2::neutral(1, &capabilities, &[]);
+        assert_eq!(report.bDevice, 1);
+        assert_eq!(report.wAxisX, 0x4000);
+        assert_eq!(report.wDial, 0x4000);
+        report.set_axis(Axis::RZ, 0x8000);
+        report.set_axis(Axis::SL0, 1);
+        assert_eq!(report.wAxisZRot, 0x8000);
+        assert_eq!(report.wSlider, 1);
+        assert_eq!(report.wAxisXRot, 0x4000);
+    }
+
+    #[test]
+    fn resting_axes_are_neutral_at_their_minimum() {
+        let capabilities = Capabilities::default();
+        let report = JOYSTICK_POSITION_V2::neutral(1, &capabilities, &[Axis::SL1, Axis::WHL]);
+        assert_eq!(report.wDial, 1);
+        assert_eq!(report.wWheel, 1);
+        assert_eq!(report.wSlider, 0x4000);
+        assert_eq!(report.wAxisX, 0x4000);
+    }
+
+    #[test]
+    fn report_sets_buttons() {
+        let mut report = JOYSTICK_POSITION_V2::default();
+        report.set_btn(1, true);
+        report.set_btn(32, true);
+        report.set_btn(33, true);
+        report.set_btn(128, true);
+        assert_eq!(report.lButtons as u32, 0x8000_0001);
+        assert_eq!(report.lButtonsEx1, 1);
+        assert_eq!(report.lButtonsEx3 as u32, 0x8000_0000);
+        report.set_btn(32, false);
+        assert_eq!(report.lButtons, 1);
+    }
+
+    #[test]
+    fn report_sets_hats() {
+        let mut report = JOYSTICK_POSITION_V2::neutral(1, &Capabilities::default(), &[]);
+        assert_eq!(report.bHats & 0xFFFF, 0xFFFF);
+        report.set_pov(1, PovDirection::EAST);
+        report.set_pov(2, PovDirection::WEST);
+        assert_eq!(report.bHats & 0xFFFF, 0xFF31);
+        report.set_pov(2, PovDirection::NEUTRAL);
+        assert_eq!(report.bHats & 0xFFFF, 0xFFF1);
+    }
+
+    fn small_joystick() -> Joystick {
+        let capabilities = Capabilities {
+            axes: vec![(Axis::X, 1, 0x8000), (Axis::SL0, 1, 0x8000)],
+            buttons: 8,
+            discrete_povs: 1,
+            continuous_povs: 0,
+        };
+        Joystick::with_capabilities(1, capabilities)
+    }
+
+    #[test]
+    fn refuses_axes_the_device_lacks() {
+        let mut joystick = small_joystick();
+        assert_eq!(joystick.set_axis(Axis::X, 0.5), Ok(()));
+        assert_eq!(joystick.set_axis_unit(Axis::SL0, 0.5), Ok(()));
+        assert_eq!(
+            joystick.set_axis(Axis::Y, 0.5),
+            Err(Error::AxisMissing(1, Axis::Y))
+        );
+        assert_eq!(
+            joystick.set_axis_unit(Axis::SL1, 0.5),
+            Err(Error::AxisMissing(1, Axis::SL1))
+        );
+    }
+
+    #[test]
+    fn refuses_buttons_out_of_range() {
+        let mut joystick = small_joystick();
+        assert_eq!(joystick.set_btn(1, true), Ok(()));
+        assert_eq!(joystick.set_btn(8, true), Ok(()));
+        // Buttons count from 1
+        assert_eq!(
+            joystick.set_btn(0, true),
+            Err(Error::ButtonOutOfRange(1, 0))
+        );
+        assert_eq!(
+            joystick.set_btn(9, true),
+            Err(Error::ButtonOutOfRange(1, 9))
+        );
+    }
+
+    #[test]
+    fn refuses_hats_out_of_range() {
+        let mut joystick = small_joystick();
+        assert_eq!(joystick.set_pov(1, PovDirection::NORTH), Ok(()));
+        assert_eq!(
+            joystick.set_pov(0, PovDirection::NORTH),
+            Err(Error::PovOutOfRange(1, 0))
+        );
+        assert_eq!(
+            joystick.set_pov(2, PovDirection::NORTH),
+            Err(Error::PovOutOfRange(1, 2))
+        );
+    }
+
+    #[test]
+    fn resting_an_axis_leaves_the_others_alone() -> Result<(), Error> {
+        let mut joystick = small_joystick();
+        joystick.rest_at_minimum(vec![Axis::SL0]);
+        joystick.set_axis(Axis::X, 1.0)?;
+        joystick.set_axis_unit(Axis::SL0, 1.0)?;
+        joystick.set_btn(1, true)?;
+        joystick.rest_axis(Axis::X);
+        assert_eq!(joystick.report.wAxisX, 0x4000);
+        assert_eq!(joystick.report.wSlider, 0x8000);
+        joystick.rest_axis(Axis::SL0);
+        assert_eq!(joystick.report.wSlider, 1);
+        assert_eq!(joystick.report.lButtons, 1);
+        Ok(())
+    }
+
     #[test]
-    fn it_works() -> Result<(), String> {
+    fn it_works() -> Result<(), Error> {
         let mut joystick = Joystick::new(1);
         joystick.acquire()?;
         joystick.reset()?;
-        joystick.set_axis(Axis::X, 1000)?;
-        joystick.set_axis(Axis::Y, 3000)?;
-        joystick.set_axis(Axis::Z, 5000)?;
-        joystick.set_axis(Axis::RX, 9000)?;
-        joystick.set_axis(Axis::RY, 11000)?;
-        joystick.set_axis(Axis::RZ, 13000)?;
-        joystick.set_axis(Axis::SL0, 17000)?;
-        joystick.set_axis(Axis::SL1, 19000)?;
+        joystick.set_axis(Axis::X, -0.9)?;
+        joystick.set_axis(Axis::Y, -0.8)?;
+        joystick.set_axis(Axis::Z, -0.7)?;
+        joystick.set_axis(Axis::RX, -0.4)?;
+        joystick.set_axis(Axis::RY, -0.3)?;
+        joystick.set_axis(Axis::RZ, -0.2)?;
+        joystick.set_axis_unit(Axis::SL0, 0.5)?;
+        joystick.set_axis_unit(Axis::SL1, 0.6)?;
+        joystick.flush()?;
         Ok(())
     }
 }
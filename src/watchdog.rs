@@ -0,0 +1,141 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+struct Fed {
+    at: Instant,
+    // Whether the watchdog has centred the device since it was last fed
+    neutral: bool,
+    // Nothing is meant to be output, e.g. while paused
+    suspended: bool,
+}
+
+// Centres the vJoy device from its own thread if nothing has been output for
+// a while, e.g. because the window is being dragged.
+pub struct Watchdog {
+    fed: Arc<Mutex<Fed>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Watchdog {
+    // Calls `on_stall` once each time it goes off. A timeout of 0 or less
+    // turns the watchdog off.
+    pub fn spawn<F>(timeout: f32, mut on_stall: F) -> Watchdog
+    where
+        F: FnMut() + Send + 'static,
+    {
+        let fed = Arc::new(Mutex::new(Fed {
+            at: Instant::now(),
+            neutral: false,
+            suspended: false,
+        }));
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = match timeout > 0.0 {
+            true => {
+                let fed = fed.clone();
+                let stop = stop.clone();
+                let timeout = Duration::from_secs_f32(timeout);
+                Some(thread::spawn(move || {
+                    while !stop.load(Ordering::SeqCst) {
+                        thread::sleep(timeout / 4);
+                        let mut fed = fed.lock().unwrap();
+                        if !fed.neutral && !fed.suspended && fed.at.elapsed() > timeout {
+                            log!("No output for {:.1}s, centring", timeout.as_secs_f32());
+                            on_stall();
+                            fed.neutral = true;
+                        }
+                    }
+                }))
+            }
+            false => None,
+        };
+        Watchdog { fed, stop, thread }
+    }
+
+    // Call whenever a report has been sent to the device. Returns true if the
+    // watchdog centred it since last time, so that report needs sending again.
+    pub fn feed(&self) -> bool {
+        let mut fed = self.fed.lock().unwrap();
+        fed.at = Instant::now();
+        fed.suspended = false;
+        if fed.neutral {
+            log!("Output resumed");
+            fed.neutral = false;
+            return true;
+        }
+        false
+    }
+
+    // Call while ocujoy deliberately isn't writing to the device, e.g. while
+    // paused or waiting for the Oculus runtime. It stays quiet until next fed.
+    pub fn suspend(&self) {
+        self.fed.lock().unwrap().suspended = true;
+    }
+
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    fn counting(timeout: f32) -> (Watchdog, Arc<AtomicUsize>) {
+        let stalls = Arc::new(AtomicUsize::new(0));
+        let counter = stalls.clone();
+        let watchdog = Watchdog::spawn(timeout, move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        (watchdog, stalls)
+    }
+
+    #[test]
+    fn goes_off_once_when_not_fed() {
+        let (mut watchdog, stalls) = counting(0.02);
+        thread::sleep(Duration::from_millis(150));
+        assert_eq!(stalls.load(Ordering::SeqCst), 1);
+        assert!(watchdog.feed());
+        assert!(!watchdog.feed());
+        watchdog.stop();
+    }
+
+    #[test]
+    fn stays_quiet_while_fed() {
+        let (mut watchdog, stalls) = counting(0.05);
+        for _ in 0..30 {
+            thread::sleep(Duration::from_millis(5));
+            assert!(!watchdog.feed());
+        }
+        watchdog.stop();
+        assert_eq!(stalls.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn stays_quiet_while_suspended() {
+        let (mut watchdog, stalls) = counting(0.02);
+        watchdog.suspend();
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(stalls.load(Ordering::SeqCst), 0);
+        assert!(!watchdog.feed());
+        // Feeding it wakes it up again
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(stalls.load(Ordering::SeqCst), 1);
+        watchdog.stop();
+    }
+
+    #[test]
+    fn zero_timeout_turns_it_off() {
+        let (mut watchdog, stalls) = counting(0.0);
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(stalls.load(Ordering::SeqCst), 0);
+        assert!(!watchdog.feed());
+        watchdog.stop();
+    }
+}
@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+use crate::minmax;
+use crate::transform::Transform;
+use crate::vjoy::Axis;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct WheelConfig {
+    // Degrees from full left lock to full right lock
+    pub lock_to_lock: f32,
+    // Takes over from the flight axis of the same name in wheel mode
+    pub steering_axis: Axis,
+    // Right trigger. The defaults for these two are the axes `--input` puts
+    // the triggers on, so the two agree.
+    pub throttle_axis: Axis,
+    // Left trigger
+    pub brake_axis: Axis,
+}
+
+impl Default for WheelConfig {
+    fn default() -> WheelConfig {
+        WheelConfig {
+            lock_to_lock: 540.0,
+            steering_axis: Axis::X,
+            throttle_axis: Axis::SL1,
+            brake_axis: Axis::SL0,
+        }
+    }
+}
+
+pub struct Wheel {
+    // Radians turned since both hands took hold, positive to the left
+    angle: f32,
+    last: Option<f32>,
+}
+
+impl Wheel {
+    pub fn new() -> Wheel {
+        Wheel {
+            angle: 0.0,
+            last: None,
+        }
+    }
+
+    // Steering from -1 (full left lock) to 1 (full right lock). The angle is
+    // tracked tick to tick so the wheel can turn further than half a turn.
+    pub fn update(&mut self, config: &WheelConfig, hands: Option<[&Transform; 2]>) -> f32 {
+        let lock = config.lock_to_lock.to_radians() / 2.0;
+        match hands {
+            Some(hands) => {
+                let line = hands[1].pos - hands[0].pos;
+                let angle = line.y.atan2(line.x);
+                if let Some(last) = self.last {
+                    let mut delta = angle - last;
+                    if delta > PI {
+                        delta -= 2.0 * PI;
+                    } else if delta < -PI {
+                        delta += 2.0 * PI;
+                    }
+                    self.angle = minmax(self.angle + delta, -lock, lock);
+                }
+                self.last = Some(angle);
+            }
+            None => {
+                self.angle = 0.0;
+                self.last = None;
+            }
+        }
+        -self.angle / lock
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transform::{at, rounded};
+
+    // Hands 40 cm apart, the line between them turned `angle` anticlockwise
+    fn hands(angle: f32) -> [Transform; 2] {
+        let (x, y) = (20.0 * angle.cos(), 20.0 * angle.sin());
+        [at(-x, -y, 0.0), at(x, y, 0.0)]
+    }
+
+    fn turn(wheel: &mut Wheel, config: &WheelConfig, angle: f32) -> f32 {
+        let hands = hands(angle);
+        let steering = wheel.update(config, Some([&hands[0], &hands[1]]));
+        rounded(steering)
+    }
+
+    #[test]
+    fn steers_from_where_it_was_taken_hold_of() {
+        let config = WheelConfig::default();
+        let mut wheel = Wheel::new();
+        assert_eq!(turn(&mut wheel, &config, 0.3), 0.0);
+        // A quarter turn left out of 270 degrees of lock
+        assert_eq!(turn(&mut wheel, &config, 0.3 + PI / 2.0), -0.333);
+        assert_eq!(turn(&mut wheel, &config, 0.3 - PI / 4.0), 0.167);
+    }
+
+    #[test]
+    fn turns_past_half_a_turn_up_to_the_lock() {
+        let config = WheelConfig::default();
+        let mut wheel = Wheel::new();
+        let steps = [0.0, 0.5, 1.0, 1.5, 2.0]
+            .iter()
+            .map(|turns| turn(&mut wheel, &config, -PI * turns));
+        assert_eq!(steps.collect::<Vec<_>>(), vec![0.0, 0.333, 0.667, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn letting_go_centres_it() {
+        let config = WheelConfig::default();
+        let mut wheel = Wheel::new();
+        turn(&mut wheel, &config, 0.0);
+        turn(&mut wheel, &config, 1.0);
+        assert_eq!(wheel.update(&config, None), 0.0);
+        assert_eq!(turn(&mut wheel, &config, 2.0), 0.0);
+    }
+}
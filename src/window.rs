@@ -0,0 +1,169 @@
+use ggez::event::{self, KeyCode, KeyMods};
+use ggez::{graphics, nalgebra as na, Context, GameResult};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+
+use crate::profile::Mode;
+
+// What the debug window draws, copied out by the mapping thread every tick
+#[derive(Clone, Default)]
+pub struct Snapshot {
+    // Hand positions in cm
+    pub left: [f32; 2],
+    pub right: [f32; 2],
+    // Radians
+    pub pitch: f32,
+    pub roll: f32,
+    pub yaw: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub mode: Mode,
+    pub paused: bool,
+    pub prompt: Option<String>,
+    pub tracked: [bool; 2],
+    pub trims: [f32; 6],
+    // Why ocujoy isn't outputting anything, while it's waiting to reconnect
+    pub problems: Vec<String>,
+}
+
+// Keys pressed in the debug window, handled by the mapping thread
+pub enum Request {
+    NextMode,
+    Trim,
+    CalibrateRanges,
+}
+
+pub struct Window {
+    snapshot: Arc<Mutex<Snapshot>>,
+    requests: Sender<Request>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Window {
+    pub fn new(
+        snapshot: Arc<Mutex<Snapshot>>,
+        requests: Sender<Request>,
+        stop: Arc<AtomicBool>,
+    ) -> Window {
+        Window {
+            snapshot,
+            requests,
+            stop,
+        }
+    }
+}
+
+impl event::EventHandler for Window {
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        if self.stop.load(Ordering::SeqCst) {
+            event::quit(ctx);
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        let state = self.snapshot.lock().unwrap().clone();
+        graphics::clear(ctx, [0.0, 0.0, 0.0, 1.0].into());
+        let red_circle = graphics::Mesh::new_circle(
+            ctx,
+            graphics::DrawMode::fill(),
+            na::Point2::new(0.0, 0.0),
+            5.0,
+            2.0,
+            graphics::Color::new(1.0, 0.0, 0.0, 1.0),
+        )?;
+        let green_circle = graphics::Mesh::new_circle(
+            ctx,
+            graphics::DrawMode::fill(),
+            na::Point2::new(0.0, 0.0),
+            5.0,
+            2.0,
+            graphics::Color::new(0.0, 1.0, 0.0, 1.0),
+        )?;
+
+        for (i, axis) in [state.pitch, state.roll, state.yaw].iter().enumerate() {
+            let length = 30f32;
+            let p1 = na::Point2::new(i as f32 * 50.0 + 325.0, 350.0);
+            let p2 = p1 + na::Vector2::new(axis.cos(), axis.sin()) * length;
+            let line = graphics::Mesh::new_line(ctx, &[p1, p2], 2.0, ggez::graphics::WHITE)?;
+            graphics::draw(ctx, &line, graphics::DrawParam::default())?;
+        }
+
+        for (i, axis) in [state.x, state.y, state.z].iter().enumerate() {
+            let length = 30f32;
+            let p1 = na::Point2::new(i as f32 * 50.0 + 475.0, 350.0 - axis * 100.0);
+            let p2 = p1 + na::Vector2::new(length, 0.0);
+            let line = graphics::Mesh::new_line(ctx, &[p1, p2], 2.0, ggez::graphics::WHITE)?;
+            graphics::draw(ctx, &line, graphics::DrawParam::default())?;
+        }
+
+        graphics::draw(
+            ctx,
+            &green_circle,
+            (na::Point2::new(state.left[0], state.left[1]),),
+        )?;
+        graphics::draw(
+            ctx,
+            &red_circle,
+            (na::Point2::new(state.right[0], state.right[1]),),
+        )?;
+        if let Some(prompt) = &state.prompt {
+            let text = graphics::Text::new(prompt.as_str());
+            graphics::draw(ctx, &text, (na::Point2::new(10.0, 10.0),))?;
+        }
+        for (i, problem) in state.problems.iter().enumerate() {
+            let text = graphics::Text::new(problem.as_str());
+            graphics::draw(ctx, &text, (na::Point2::new(10.0, 30.0 + 20.0 * i as f32),))?;
+        }
+        let lost: Vec<&str> = ["left", "right"]
+            .iter()
+            .enumerate()
+            .filter(|&(hand, _)| !state.tracked[hand])
+            .map(|(_, &name)| name)
+            .collect();
+        if !lost.is_empty() {
+            let text = graphics::Text::new(format!("Tracking lost: {}", lost.join(", ")));
+            graphics::draw(ctx, &text, (na::Point2::new(10.0, 530.0),))?;
+        }
+        if state.trims.iter().any(|&trim| trim != 0.0) {
+            let names = ["X", "Y", "Z", "rX", "rY", "rZ"];
+            let trims: Vec<String> = names
+                .iter()
+                .zip(state.trims.iter())
+                .map(|(name, trim)| format!("{} {:+.2}", name, trim))
+                .collect();
+            let text = graphics::Text::new(format!("Trim: {}", trims.join("  ")));
+            graphics::draw(ctx, &text, (na::Point2::new(10.0, 550.0),))?;
+        }
+        let mode = match state.paused {
+            true => graphics::Text::new(format!("{:?} (paused)", state.mode)),
+            false => graphics::Text::new(format!("{:?}", state.mode)),
+        };
+        graphics::draw(ctx, &mode, (na::Point2::new(10.0, 570.0),))?;
+        graphics::present(ctx)?;
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: KeyCode,
+        _keymods: KeyMods,
+        _repeat: bool,
+    ) {
+        let request = match keycode {
+            KeyCode::M => Request::NextMode,
+            KeyCode::T => Request::Trim,
+            KeyCode::R => Request::CalibrateRanges,
+            _ => return,
+        };
+        let _ = self.requests.send(request);
+    }
+
+    fn quit_event(&mut self, _ctx: &mut Context) -> bool {
+        self.stop.store(true, Ordering::SeqCst);
+        false
+    }
+}
@@ -0,0 +1,98 @@
+use ggez::nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+use crate::minmax;
+use crate::transform::{elevation, Transform};
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct YokeConfig {
+    // Radians the yoke turns for full roll
+    pub roll_angle: f32,
+    // cm pushed or pulled for full pitch
+    pub pitch_travel: f32,
+    // Twisting the yoke about the vertical gives yaw
+    pub yaw: bool,
+    pub yaw_angle: f32,
+}
+
+impl Default for YokeConfig {
+    fn default() -> YokeConfig {
+        YokeConfig {
+            roll_angle: PI / 2.0,
+            pitch_travel: 15.0,
+            yaw: false,
+            yaw_angle: PI / 4.0,
+        }
+    }
+}
+
+// Pitch, roll and yaw from -1 to 1, given where both hands gripped and where they are now
+pub fn deflection(
+    config: &YokeConfig,
+    refs: [&Transform; 2],
+    hands: [&Transform; 2],
+) -> (f32, f32, f32) {
+    let start_line = refs[1].pos - refs[0].pos;
+    let line = hands[1].pos - hands[0].pos;
+    let start_centre = (refs[0].pos.coords + refs[1].pos.coords) / 2.0;
+    let centre = (hands[0].pos.coords + hands[1].pos.coords) / 2.0;
+
+    let pitch = centre.z - start_centre.z;
+    let roll = elevation(&start_line) - elevation(&line);
+    let yaw = match config.yaw {
+        true => heading(&start_line) - heading(&line),
+        false => 0.0,
+    };
+
+    (
+        minmax(pitch / config.pitch_travel, -1.0, 1.0),
+        minmax(roll / config.roll_angle, -1.0, 1.0),
+        minmax(yaw / config.yaw_angle, -1.0, 1.0),
+    )
+}
+
+// Angle of the line between the hands about the vertical, positive with the right hand forward
+fn heading(line: &Vector3<f32>) -> f32 {
+    (-line.z).atan2(line.x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transform::{at, rounded};
+
+    fn deflect(config: &YokeConfig, left: Transform, right: Transform) -> (f32, f32, f32) {
+        let refs = [at(-20.0, 0.0, 0.0), at(20.0, 0.0, 0.0)];
+        let (pitch, roll, yaw) = deflection(config, [&refs[0], &refs[1]], [&left, &right]);
+        (rounded(pitch), rounded(roll), rounded(yaw))
+    }
+
+    #[test]
+    fn pushing_pitches() {
+        let config = YokeConfig::default();
+        let pushed = deflect(&config, at(-20.0, 0.0, -7.5), at(20.0, 0.0, -7.5));
+        assert_eq!(pushed, (-0.5, 0.0, 0.0));
+        let pulled = deflect(&config, at(-20.0, 0.0, 30.0), at(20.0, 0.0, 30.0));
+        assert_eq!(pulled, (1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn turning_rolls() {
+        let config = YokeConfig::default();
+        let turned = deflect(&config, at(-20.0, 20.0, 0.0), at(20.0, -20.0, 0.0));
+        assert_eq!(turned, (0.0, 0.5, 0.0));
+    }
+
+    #[test]
+    fn twisting_yaws_only_when_enabled() {
+        let mut config = YokeConfig::default();
+        let twisted = || (at(-20.0, 0.0, 20.0), at(20.0, 0.0, -20.0));
+        let (left, right) = twisted();
+        assert_eq!(deflect(&config, left, right), (0.0, 0.0, 0.0));
+        config.yaw = true;
+        let (left, right) = twisted();
+        assert_eq!(deflect(&config, left, right), (0.0, 0.0, -1.0));
+    }
+}